@@ -4,5 +4,6 @@ export interface Debt {
   repayment: number
   amount: number
   last_timestamp: number
+  remaining: number
   last_date: string
 }
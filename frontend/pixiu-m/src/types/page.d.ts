@@ -1,6 +1,7 @@
 export interface Page<T> {
   total: number
   data: T[]
+  next_cursor: string | null
   sum: {
     name: string
     value: number
@@ -2,4 +2,9 @@ export interface Property {
   id: number
   name: string
   amount: number
+  opening_balance: number
+  opening_timestamp: number
+  archived: boolean
+  currency: string
+  base_amount: number
 }
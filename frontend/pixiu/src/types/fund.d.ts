@@ -6,4 +6,8 @@ export interface Fund {
   timestamp: number
   date: string
   source: string
+  currency: string
+  tags: string[]
+  splits: { class: string; amount: number }[]
+  reconciled: boolean
 }
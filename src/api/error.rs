@@ -7,8 +7,37 @@ use axum::{
 #[derive(Debug)]
 pub struct AppError(pub Error);
 
+/// 需要告知调用方的错误，例如参数错误、记录不存在、记录已锁定
+#[derive(Debug)]
+pub struct StatusError(pub StatusCode, pub String);
+
+impl StatusError {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        StatusError(StatusCode::BAD_REQUEST, message.into())
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        StatusError(StatusCode::NOT_FOUND, message.into())
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        StatusError(StatusCode::CONFLICT, message.into())
+    }
+}
+
+impl std::fmt::Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.0, self.1)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let Some(StatusError(status, message)) = self.0.downcast_ref::<StatusError>() {
+            return (*status, message.clone()).into_response();
+        }
         // 打印调用栈和错误信息
         eprintln!("Internal error: {:#}", self.0);
         (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error").into_response()
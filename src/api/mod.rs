@@ -1,31 +1,25 @@
 use axum::{
-    extract::{Json, Query, State, Path},
     http::{HeaderValue, Method, StatusCode, Uri},
     response::{IntoResponse, Response},
-    routing::{get, post, delete, put},
+    routing::get,
     Router,
 };
 use mime_guess::from_path;
 use rust_embed::RustEmbed;
-use sqlx::MySqlPool;
 use tower_http::cors::CorsLayer;
 
+use crate::storage::Database;
+
 mod error;
-mod pixiu;
-use error::AppError;
+pub mod mysql;
+pub mod sqlite;
 
-pub fn app(pool: MySqlPool) -> Router {
-    Router::new()
-        .route("/pixiu/init", post(pixiu_init))
-        .route("/pixiu/fund", post(pixiu_insert_fund_info))
-        .route("/pixiu/fund", get(pixiu_get_fund_info))
-        .route("/pixiu/fund/{id}", delete(pixiu_delete_fund_info))
-        .route("/pixiu/fund/{id}", put(pixiu_update_fund_info))
-        .route("/pixiu/fund/sources", get(pixiu_get_fund_sources))
-        .route("/pixiu/fund/types", get(pixiu_get_fund_types))
-        .route("/pixiu/debt", get(pixiu_get_debt_info))
-        .route("/pixiu/property", get(pixiu_get_property_info))
-        .with_state(pool.clone())
+pub fn app(db: Database) -> Router {
+    let router = match db {
+        Database::MySql(pool) => mysql::routes::router().with_state(pool),
+        Database::Sqlite(pool) => sqlite::routes::router().with_state(pool),
+    };
+    router
         .layer(
             CorsLayer::new()
                 .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
@@ -41,144 +35,28 @@ pub fn app(pool: MySqlPool) -> Router {
         .fallback(get(frontend_router))
 }
 
-async fn pixiu_init(State(pool): State<MySqlPool>) -> Result<(), AppError> {
-    pixiu::init(&pool).await?;
-    Ok(())
-}
-
-async fn pixiu_insert_fund_info(
-    State(pool): State<MySqlPool>,
-    Json(payload): Json<pixiu::FundInfo>,
-) -> Result<(), AppError> {
-    pixiu::insert_fund_info(&pool, payload).await?;
-    Ok(())
-}
-
-async fn pixiu_get_fund_info(
-    State(pool): State<MySqlPool>,
-    Query(params): Query<PageRequest>,
-) -> Result<Json<PageResponse<pixiu::FundInfo>>, AppError> {
-    let total = pixiu::count(
-        &pool,
-        params.from,
-        params.to,
-        params.source.clone(),
-        params.fund_type.clone(),
-        params.name.clone(),
-    )
-    .await?;
-    let funds = pixiu::get_fund_info(
-        &pool,
-        params.from,
-        params.to,
-        params.page,
-        params.size,
-        params.source.clone(),
-        params.fund_type.clone(),
-        params.name.clone(),
-    )
-    .await?;
-    let sums = pixiu::get_sum_info(
-        &pool,
-        params.from,
-        params.to,
-        params.source.clone(),
-        params.fund_type.clone(),
-        params.name.clone(),
-    )
-    .await?;
-    let income = pixiu::get_income_info(
-        &pool,
-        params.from,
-        params.to,
-        params.source.clone(),
-        params.fund_type.clone(),
-        params.name.clone(),
-    )
-    .await?;
-    let expenses = pixiu::get_expense_info(
-        &pool,
-        params.from,
-        params.to,
-        params.source.clone(),
-        params.fund_type.clone(),
-        params.name.clone(),
-    )
-    .await?;
-    let response = PageResponse {
-        total,
-        data: funds,
-        sum: sums,
-        income,
-        expenses,
-    };
-    Ok(Json(response))
-}
-
-async fn pixiu_get_debt_info(
-    State(pool): State<MySqlPool>,
-) -> Result<Json<Vec<pixiu::DebtInfo>>, AppError> {
-    let debts = pixiu::get_debt_info(&pool).await?;
-    Ok(Json(debts))
-}
-
-async fn pixiu_get_property_info(
-    State(pool): State<MySqlPool>,
-) -> Result<Json<Vec<pixiu::PropertyInfo>>, AppError> {
-    let properties = pixiu::get_property_info(&pool).await?;
-    Ok(Json(properties))
-}
-
-async fn pixiu_get_fund_sources(
-    State(pool): State<MySqlPool>,
-) -> Result<Json<Vec<String>>, AppError> {
-    let sources = pixiu::get_fund_sources(&pool).await?;
-    Ok(Json(sources))
-}
-
-async fn pixiu_get_fund_types(
-    State(pool): State<MySqlPool>,
-) -> Result<Json<Vec<String>>, AppError> {
-    let types = pixiu::get_fund_types(&pool).await?;
-    Ok(Json(types))
-}
-
-async fn pixiu_delete_fund_info(
-    State(pool): State<MySqlPool>,
-    Path(id): Path<u32>,
-) -> Result<(), AppError> {
-    pixiu::delete_fund_info(&pool, id).await?;
-    Ok(())
-}
-
-async fn pixiu_update_fund_info(
-    State(pool): State<MySqlPool>,
-    Path(id): Path<u32>,
-    Json(payload): Json<pixiu::FundInfo>,
-) -> Result<(), AppError> {
-    pixiu::update_fund_info(&pool, id, payload).await?;
-    Ok(())
+/// 生成到期的周期记账，返回生成的条数
+pub async fn post_recurring(db: &Database, now: i64) -> anyhow::Result<usize> {
+    match db {
+        Database::MySql(pool) => mysql::routes::pixiu::recurring::post_due(pool, now).await,
+        Database::Sqlite(pool) => sqlite::routes::pixiu::recurring::post_due(pool, now).await,
+    }
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-pub struct PageRequest {
-    from: i64,
-    to: i64,
-    page: u32,
-    size: u32,
-    source: Option<String>,
-    #[serde(rename = "type")]
-    fund_type: Option<String>,
-    name: Option<String>,
+/// 记录当天的资产与负债快照
+pub async fn snapshot_net_worth(db: &Database, now: i64) -> anyhow::Result<()> {
+    match db {
+        Database::MySql(pool) => mysql::routes::pixiu::net_worth::snapshot(pool, now).await,
+        Database::Sqlite(pool) => sqlite::routes::pixiu::net_worth::snapshot(pool, now).await,
+    }
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-pub struct PageResponse<T> {
-    total: i32,
-    data: Vec<T>,
-    sum: Vec<pixiu::SumInfo>,
-    income: f32,
-    expenses: f32,
+/// 彻底删除 `before` 之前删除的资金记录，返回删除的条数
+pub async fn purge_deleted(db: &Database, before: i64) -> anyhow::Result<u64> {
+    match db {
+        Database::MySql(pool) => mysql::routes::pixiu::purge_deleted(pool, before).await,
+        Database::Sqlite(pool) => sqlite::routes::pixiu::purge_deleted(pool, before).await,
+    }
 }
 
 #[derive(RustEmbed)]
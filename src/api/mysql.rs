@@ -0,0 +1,9 @@
+use crate::storage::Dialect;
+
+type Db = sqlx::MySql;
+const DIALECT: Dialect = Dialect::MySql;
+
+/// 记账接口的 MySQL 实现，与 SQLite 共用 `routes.rs` 及 `pixiu` 的代码
+#[allow(clippy::duplicate_mod)]
+#[path = "routes.rs"]
+pub mod routes;
@@ -1,305 +1,1025 @@
-use sqlx::MySqlPool;
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use sqlx::{Pool, QueryBuilder, Transaction};
+
+use super::{Db, DIALECT};
+use crate::{
+    api::error::StatusError,
+    storage::{InsertId, Money, NullableMoney},
+};
+use split::FUND_LINES_SQL;
+
+pub mod attachment;
+pub mod audit;
+pub mod batch;
+pub mod budget;
+pub mod category;
+pub mod currency;
+pub mod export;
+pub mod import;
+pub mod loan;
+pub mod net_worth;
+pub mod page;
+pub mod reconcile;
+pub mod recurring;
+pub mod report;
+pub mod rule;
+pub mod split;
+pub mod tag;
+pub mod transfer;
 
 #[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
 pub struct FundInfo {
     id: Option<u32>,
-    amount: f32,
+    #[sqlx(try_from = "Money")]
+    amount: Decimal,
     name: String,
     class: String,
     timestamp: i64,
     source: String,
+    /// 为空时取资产账户的币种
+    #[serde(default)]
+    currency: String,
+    #[sqlx(skip)]
+    #[serde(default)]
+    tags: Vec<String>,
+    /// 按分类拆分，为空表示不拆分
+    #[sqlx(skip)]
+    #[serde(default)]
+    splits: Vec<split::FundSplit>,
+    /// 已被余额断言核对，不能修改或删除
+    #[serde(default)]
+    reconciled: bool,
 }
 
+#[cfg(test)]
+impl FundInfo {
+    /// 测试用的资金记录，其余字段按需用结构体更新语法覆盖
+    fn sample(amount: Decimal, name: &str) -> Self {
+        FundInfo {
+            id: None,
+            amount,
+            name: name.to_string(),
+            class: "餐饮美食".to_string(),
+            // 2023-12-31 16:30:00 +08:00
+            timestamp: 1704011400,
+            source: "支付宝".to_string(),
+            currency: "CNY".to_string(),
+            tags: vec![],
+            splits: vec![],
+            reconciled: false,
+        }
+    }
+}
+
+/// 欠款，`repayment`、`last_timestamp`、`remaining` 由还款记录汇总得出
 #[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
 pub struct DebtInfo {
     id: Option<u32>,
     name: String,
-    amount: f32,
-    repayment: f32,
+    #[sqlx(try_from = "Money")]
+    amount: Decimal,
+    #[serde(default)]
+    #[sqlx(try_from = "Money")]
+    repayment: Decimal,
+    #[serde(default)]
     last_timestamp: i64,
+    #[serde(default)]
+    #[sqlx(try_from = "Money")]
+    remaining: Decimal,
+    /// 以下为贷款条款，用于生成还款计划
+    #[sqlx(try_from = "NullableMoney")]
+    principal: Option<Decimal>,
+    /// 年利率，百分比
+    annual_rate: Option<f32>,
+    term_months: Option<u32>,
+    method: Option<loan::RepaymentMethod>,
+    /// 首次还款时间
+    start_timestamp: Option<i64>,
+}
+
+impl DebtInfo {
+    /// 贷款条款齐全时返回贷款，还款计划按定点数计算、逐期舍入到分
+    pub fn loan(&self) -> Option<loan::Loan> {
+        Some(loan::Loan {
+            principal: self.principal?,
+            annual_rate: Decimal::from_f32(self.annual_rate?)?,
+            term_months: self.term_months?,
+            method: self.method?,
+            start_timestamp: self.start_timestamp?,
+        })
+    }
+
+    /// 检查填写了的贷款条款，超出范围时返回 400
+    fn validate(&self) -> anyhow::Result<()> {
+        if self
+            .term_months
+            .is_some_and(|term| term == 0 || term > loan::MAX_TERM_MONTHS)
+        {
+            let message = format!(
+                "term_months must be between 1 and {}",
+                loan::MAX_TERM_MONTHS
+            );
+            return Err(StatusError::bad_request(message).into());
+        }
+        match self.loan() {
+            Some(loan) => loan.validate(),
+            None => Ok(()),
+        }
+    }
 }
 
+/// 还款记录
+#[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
+pub struct DebtRepayment {
+    id: Option<u32>,
+    #[serde(default)]
+    debt_id: u32,
+    #[sqlx(try_from = "Money")]
+    amount: Decimal,
+    timestamp: i64,
+}
+
+/// 贷款还款计划
+#[derive(Debug, serde::Serialize)]
+pub struct DebtSchedule {
+    schedule: loan::Schedule,
+    prepayment: Option<loan::Prepayment>,
+}
+
+/// 资产账户，`name` 对应资金记录的 `source`
+///
+/// `amount` 为当前余额：期初余额加上期初时间之后的资金记录
 #[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
 pub struct PropertyInfo {
     id: Option<u32>,
     name: String,
-    amount: f32,
+    #[serde(default)]
+    #[sqlx(try_from = "Money")]
+    amount: Decimal,
+    #[sqlx(try_from = "Money")]
+    opening_balance: Decimal,
+    #[serde(default)]
+    opening_timestamp: i64,
+    #[serde(default)]
+    archived: bool,
+    #[serde(default = "currency::default_currency")]
+    currency: String,
+    /// 折合为统计本位币的余额
+    #[sqlx(default, try_from = "Money")]
+    #[serde(default)]
+    base_amount: Decimal,
 }
 
 #[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
 pub struct SumInfo {
     name: String,
-    value: f32,
-}
-
-pub async fn init(pool: &MySqlPool) -> anyhow::Result<()> {
-    let sql = "CREATE TABLE IF NOT EXISTS pixiu_fund_info (
-        id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
-        name VARCHAR(255) NOT NULL,
-        amount FLOAT NOT NULL,
-        class VARCHAR(255) NOT NULL,
-        timestamp BIGINT NOT NULL,
-        source VARCHAR(255) NOT NULL
-    )";
-    sqlx::query(sql).execute(pool).await?;
-    let sql = "CREATE TABLE IF NOT EXISTS pixiu_debt_info (
-        id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
-        name VARCHAR(255) NOT NULL,
-        amount FLOAT NOT NULL,
-        repayment FLOAT NOT NULL,
-        last_timestamp BIGINT NOT NULL
-    )";
-    sqlx::query(sql).execute(pool).await?;
-    let sql = "CREATE TABLE IF NOT EXISTS pixiu_property_info (
-        id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
-        name VARCHAR(255) NOT NULL,
-        amount FLOAT NOT NULL
-    )";
-    sqlx::query(sql).execute(pool).await?;
+    #[sqlx(try_from = "Money")]
+    value: Decimal,
+}
+
+/// 资金记录的币种，依次绑定指定的币种和资金来源，未指定时取资产账户的币种
+const FUND_CURRENCY_SQL: &str = "COALESCE(NULLIF(?, ''),
+    (SELECT currency FROM pixiu_property_info WHERE name = ? LIMIT 1), 'CNY')";
+
+/// 指定了币种时统一格式
+fn fund_currency(currency: &str) -> anyhow::Result<String> {
+    match currency.is_empty() {
+        true => Ok(String::new()),
+        false => currency::normalize(currency),
+    }
+}
+
+/// 写入资金记录，先按自动分类规则补全分类、来源和标签，`client` 记入审计日志
+pub async fn insert_fund_info(
+    pool: &Pool<Db>,
+    mut info: FundInfo,
+    client: &str,
+) -> anyhow::Result<()> {
+    rule::Rules::load(pool).await?.apply(&mut info);
+    let mut tx = pool.begin().await?;
+    insert_fund(&mut tx, &info, client).await?;
+    tx.commit().await?;
+    // 记录已写入，预算提醒在后台发送
+    budget::spawn_notify(pool, vec![info]);
     Ok(())
 }
 
-pub async fn insert_fund_info(pool: &MySqlPool, info: FundInfo) -> anyhow::Result<()> {
-    let sql =
-        "INSERT INTO pixiu_fund_info (amount, name, class, timestamp, source) VALUES (?, ?, ?, ?, ?)";
-    sqlx::query(sql)
-        .bind(info.amount)
-        .bind(info.name)
-        .bind(info.class)
+/// 在事务中写入资金记录及其标签，返回新记录的 id
+async fn insert_fund(
+    tx: &mut Transaction<'_, Db>,
+    info: &FundInfo,
+    client: &str,
+) -> anyhow::Result<u32> {
+    reconcile::check_open(tx, &info.source, info.timestamp).await?;
+    let sql = format!(
+        "INSERT INTO pixiu_fund_info (amount, name, class, timestamp, source, currency)
+        VALUES (?, ?, ?, ?, ?, {FUND_CURRENCY_SQL})"
+    );
+    let result = sqlx::query(&sql)
+        .bind(Money(info.amount))
+        .bind(&info.name)
+        .bind(&info.class)
         .bind(info.timestamp)
-        .bind(info.source)
-        .execute(pool)
+        .bind(&info.source)
+        .bind(fund_currency(&info.currency)?)
+        .bind(&info.source)
+        .execute(&mut **tx)
         .await?;
-    Ok(())
+    let id = result.insert_id() as u32;
+    tag::set_fund_tags(tx, id, &info.tags).await?;
+    split::set_fund_splits(tx, id, info).await?;
+    audit::record(tx, id, audit::Action::Insert, None, client).await?;
+    Ok(id)
 }
 
-pub async fn get_fund_info(
-    pool: &MySqlPool,
-    from: i64,
-    to: i64,
-    page: u32,
-    size: u32,
-    source: Option<String>,
-    fund_type: Option<String>,
-    name: Option<String>,
-) -> anyhow::Result<Vec<FundInfo>> {
-    let offset = (page - 1) * size;
-    let mut sql = format!(
-        "SELECT * FROM pixiu_fund_info WHERE timestamp BETWEEN {} AND {}",
-        from, to
-    );
-    if let Some(source) = source {
-        if !source.is_empty() {
-            sql.push_str(&format!(" AND source = '{}'", source));
-        }
-    }
-    if let Some(fund_type) = fund_type {
-        if !fund_type.is_empty() {
-            let types: Vec<String> = fund_type.split(',').map(|s| format!("'{}'", s)).collect();
-            sql.push_str(&format!(" AND class IN ({})", types.join(",")));
+/// 资金记录的筛选条件，列表、统计、计数共用
+///
+/// 所有条件都以绑定参数的形式拼接，名称按字面量模糊匹配
+#[derive(Debug, Default, Clone)]
+pub struct FundFilter {
+    pub from: i64,
+    pub to: i64,
+    pub sources: Vec<String>,
+    /// 包含其下级分类
+    pub classes: Vec<String>,
+    pub name: Option<String>,
+    /// 带有其中任一标签
+    pub tags: Vec<String>,
+}
+
+impl FundFilter {
+    /// 由前端参数构造，`source`、`type`、`tags` 为逗号分隔的多选值
+    pub fn new(
+        from: i64,
+        to: i64,
+        source: Option<String>,
+        fund_type: Option<String>,
+        name: Option<String>,
+        tags: Option<String>,
+    ) -> Self {
+        Self {
+            from,
+            to,
+            sources: split_list(source),
+            classes: split_list(fund_type),
+            name: name.filter(|name| !name.is_empty()),
+            tags: split_list(tags),
         }
     }
-    if let Some(name) = name {
-        if !name.is_empty() {
-            sql.push_str(&format!(" AND name LIKE '%{}%'", name));
-        }
+
+    /// 追加 `WHERE ...` 条件，不含已删除的记录，拆分的记录有任一拆分的分类符合即可
+    pub(crate) fn push_where(&self, qb: &mut QueryBuilder<'_, Db>) {
+        self.push_conditions(qb, false);
     }
-    sql.push_str(&format!(
-        " order by timestamp desc, id limit {} offset {}",
-        size, offset
-    ));
 
-    let rows = sqlx::query_as(&sql).fetch_all(pool).await?;
-    Ok(rows)
-}
+    /// 查询 `split::FUND_LINES_SQL` 时使用，分类按拆分匹配
+    fn push_line_where(&self, qb: &mut QueryBuilder<'_, Db>) {
+        self.push_conditions(qb, true);
+    }
 
-pub async fn get_sum_info(
-    pool: &MySqlPool,
-    from: i64,
-    to: i64,
-    source: Option<String>,
-    fund_type: Option<String>,
-    name: Option<String>,
-) -> anyhow::Result<Vec<SumInfo>> {
-    let mut sql = format!(
-        "select class as name, sum(ceil(-amount)) as value
-        from pixiu_fund_info
-        where timestamp BETWEEN {} AND {}",
-        from, to
-    );
-    if let Some(source) = source {
-        if !source.is_empty() {
-            sql.push_str(&format!(" AND source = '{}'", source));
+    fn push_conditions(&self, qb: &mut QueryBuilder<'_, Db>, lines: bool) {
+        qb.push(" WHERE deleted_at IS NULL AND timestamp BETWEEN ")
+            .push_bind(self.from)
+            .push(" AND ")
+            .push_bind(self.to);
+        push_in(qb, "source", &self.sources);
+        if !self.classes.is_empty() && lines {
+            qb.push(" AND ");
+            category::push_class_condition(qb, &self.classes);
+        } else if !self.classes.is_empty() {
+            qb.push(" AND (");
+            category::push_class_condition(qb, &self.classes);
+            qb.push(" OR id IN (SELECT fund_id FROM pixiu_fund_split WHERE ");
+            category::push_class_condition(qb, &self.classes);
+            qb.push("))");
         }
-    }
-    if let Some(fund_type) = fund_type {
-        if !fund_type.is_empty() {
-            let types: Vec<String> = fund_type.split(',').map(|s| format!("'{}'", s)).collect();
-            sql.push_str(&format!(" AND class IN ({})", types.join(",")));
+        if let Some(pattern) = self.name_pattern() {
+            qb.push(" AND name LIKE ")
+                .push_bind(pattern)
+                .push(DIALECT.like_escape());
         }
-    }
-    if let Some(name) = name {
-        if !name.is_empty() {
-            sql.push_str(&format!(" AND name LIKE '%{}%'", name));
+        if !self.tags.is_empty() {
+            qb.push(
+                " AND id IN (SELECT pft.fund_id FROM pixiu_fund_tag pft
+                JOIN pixiu_tag pt ON pt.id = pft.tag_id",
+            );
+            push_in(qb, "pt.name", &self.tags);
+            qb.push(")");
         }
     }
-    sql.push_str(" group by class having value > 0");
-    let rows = sqlx::query_as(&sql).fetch_all(pool).await?;
-    Ok(rows)
+
+    /// 名称模糊匹配的 LIKE 模式，转义其中的通配符
+    fn name_pattern(&self) -> Option<String> {
+        self.name
+            .as_deref()
+            .map(|name| format!("%{}%", escape_like(name)))
+    }
 }
 
-pub async fn get_income_info(
-    pool: &MySqlPool,
-    from: i64,
-    to: i64,
-    source: Option<String>,
-    fund_type: Option<String>,
-    name: Option<String>,
-) -> anyhow::Result<f32> {
-    let mut sql = format!(
-        "SELECT ROUND(IFNULL(SUM(amount), 0), 2)
-        FROM pixiu_fund_info
-        WHERE timestamp BETWEEN {} AND {}
-        AND amount > 0",
-        from, to
-    );
-    if let Some(source) = source {
-        if !source.is_empty() {
-            sql.push_str(&format!(" AND source = '{}'", source));
-        }
+fn split_list(value: Option<String>) -> Vec<String> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn push_in(qb: &mut QueryBuilder<'_, Db>, column: &str, values: &[String]) {
+    if values.is_empty() {
+        return;
     }
-    if let Some(fund_type) = fund_type {
-        if !fund_type.is_empty() {
-            let types: Vec<String> = fund_type.split(',').map(|s| format!("'{}'", s)).collect();
-            sql.push_str(&format!(" AND class IN ({})", types.join(",")));
-        }
+    qb.push(format!(" AND {column} IN ("));
+    let mut separated = qb.separated(", ");
+    for value in values {
+        separated.push_bind(value.clone());
     }
-    if let Some(name) = name {
-        if !name.is_empty() {
-            sql.push_str(&format!(" AND name LIKE '%{}%'", name));
+    separated.push_unseparated(")");
+}
+
+/// 转义 LIKE 中的 `\`、`%`、`_`，转义符为 `\`
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
         }
+        escaped.push(c);
     }
-    let result: Option<f32> = sqlx::query_scalar(&sql).fetch_optional(pool).await?;
-    Ok(result.unwrap_or(0.0))
-}
-
-pub async fn get_expense_info(
-    pool: &MySqlPool,
-    from: i64,
-    to: i64,
-    source: Option<String>,
-    fund_type: Option<String>,
-    name: Option<String>,
-) -> anyhow::Result<f32> {
-    let mut sql = format!(
-        "SELECT ROUND(IFNULL(SUM(amount), 0), 2)
-        FROM pixiu_fund_info
-        WHERE timestamp BETWEEN {} AND {}
-        AND amount < 0",
-        from, to
+    escaped
+}
+
+pub async fn get_fund_info(
+    pool: &Pool<Db>,
+    filter: &FundFilter,
+    page: &page::Page,
+) -> anyhow::Result<Vec<FundInfo>> {
+    let mut qb = QueryBuilder::new("SELECT * FROM pixiu_fund_info");
+    filter.push_where(&mut qb);
+    page.push_after(&mut qb);
+    page.push_order(&mut qb);
+    let mut rows = qb.build_query_as().fetch_all(pool).await?;
+    tag::fill_tags(pool, &mut rows).await?;
+    split::fill_splits(pool, &mut rows).await?;
+    Ok(rows)
+}
+
+/// 列表页的统计，以 `base` 币种计
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct Summary {
+    /// 各分类的净支出
+    sum: Vec<SumInfo>,
+    income: Decimal,
+    expenses: Decimal,
+}
+
+/// 分类合计、收入和支出，一次聚合查询得出，指定 `level` 时下级分类汇总到该层级的上级分类
+///
+/// 拆分的记录按拆分计入各自的分类
+pub async fn get_summary(
+    pool: &Pool<Db>,
+    filter: &FundFilter,
+    level: Option<usize>,
+    base: &str,
+) -> anyhow::Result<Summary> {
+    let mut qb = QueryBuilder::new(
+        "SELECT class, currency,
+            SUM(CASE WHEN amount > 0 THEN amount ELSE 0 END),
+            SUM(CASE WHEN amount < 0 THEN amount ELSE 0 END), ",
     );
-    if let Some(source) = source {
-        if !source.is_empty() {
-            sql.push_str(&format!(" AND source = '{}'", source));
-        }
-    }
-    if let Some(fund_type) = fund_type {
-        if !fund_type.is_empty() {
-            let types: Vec<String> = fund_type.split(',').map(|s| format!("'{}'", s)).collect();
-            sql.push_str(&format!(" AND class IN ({})", types.join(",")));
+    currency::push_rate_time(&mut qb, base);
+    qb.push(format!(" FROM {FUND_LINES_SQL}"));
+    filter.push_line_where(&mut qb);
+    qb.push(" GROUP BY class, currency, rate_time");
+    let rows: Vec<(String, String, Money, Money, i64)> =
+        qb.build_query_as().fetch_all(pool).await?;
+    let mut rows: Vec<_> = rows
+        .into_iter()
+        .map(|(class, currency, income, expense, rate_time)| {
+            (class, currency, income.0, expense.0, rate_time)
+        })
+        .collect();
+    if let Some(level) = level {
+        let tree = category::Tree::load(pool).await?;
+        for row in rows.iter_mut() {
+            row.0 = tree.ancestor(&row.0, level);
         }
     }
-    if let Some(name) = name {
-        if !name.is_empty() {
-            sql.push_str(&format!(" AND name LIKE '%{}%'", name));
-        }
+    let rates = currency::Rates::load(pool).await?;
+    summarize(rows, &rates, base)
+}
+
+/// 每行按 `rate_time` 时的汇率折合
+fn summarize(
+    rows: Vec<(String, String, Decimal, Decimal, i64)>,
+    rates: &currency::Rates,
+    base: &str,
+) -> anyhow::Result<Summary> {
+    let mut summary = Summary::default();
+    let mut sums = Vec::with_capacity(rows.len());
+    for (class, currency, income, expense, rate_time) in rows {
+        summary.income += rates.convert(income, &currency, base, rate_time)?;
+        summary.expenses += rates.convert(expense, &currency, base, rate_time)?;
+        sums.push((class, currency, -(income + expense), rate_time));
     }
-    let result: Option<f32> = sqlx::query_scalar(&sql).fetch_optional(pool).await?;
-    Ok(result.unwrap_or(0.0))
-}
-
-pub async fn count(
-    pool: &MySqlPool,
-    from: i64,
-    to: i64,
-    source: Option<String>,
-    fund_type: Option<String>,
-    name: Option<String>,
-) -> anyhow::Result<i32> {
-    let mut sql = format!(
-        "SELECT COUNT(*) FROM pixiu_fund_info WHERE timestamp BETWEEN {} AND {}",
-        from, to
-    );
-    if let Some(source) = source {
-        if !source.is_empty() {
-            sql.push_str(&format!(" AND source = '{}'", source));
+    summary.sum = sum_by_name(sums, rates, base)?;
+    Ok(summary)
+}
+
+/// 各分类的支出，统计口径与列表页相同
+pub async fn get_sum_info(
+    pool: &Pool<Db>,
+    filter: &FundFilter,
+    level: Option<usize>,
+    base: &str,
+) -> anyhow::Result<Vec<SumInfo>> {
+    Ok(get_summary(pool, filter, level, base).await?.sum)
+}
+
+/// 合并各币种的合计，每行按 `rate_time` 时的汇率折合，只保留净支出为正的项
+fn sum_by_name(
+    rows: Vec<(String, String, Decimal, i64)>,
+    rates: &currency::Rates,
+    base: &str,
+) -> anyhow::Result<Vec<SumInfo>> {
+    let mut sums: Vec<SumInfo> = vec![];
+    for (name, currency, value, rate_time) in rows {
+        let value = rates.convert(value, &currency, base, rate_time)?;
+        match sums.iter_mut().find(|sum| sum.name == name) {
+            Some(sum) => sum.value += value,
+            None => sums.push(SumInfo { name, value }),
         }
     }
-    if let Some(fund_type) = fund_type {
-        if !fund_type.is_empty() {
-            let types: Vec<String> = fund_type.split(',').map(|s| format!("'{}'", s)).collect();
-            sql.push_str(&format!(" AND class IN ({})", types.join(",")));
-        }
+    sums.retain(|sum| sum.value > Decimal::ZERO);
+    Ok(sums)
+}
+
+pub async fn count(pool: &Pool<Db>, filter: &FundFilter) -> anyhow::Result<i32> {
+    let mut qb = QueryBuilder::new("SELECT COUNT(*) FROM pixiu_fund_info");
+    filter.push_where(&mut qb);
+    let count: i32 = qb.build_query_scalar().fetch_one(pool).await?;
+    Ok(count)
+}
+
+const DEBT_INFO_SQL: &str = "SELECT
+        pdi.id,
+        pdi.name,
+        pdi.amount,
+        COALESCE(SUM(pdr.amount), 0) AS repayment,
+        COALESCE(MAX(pdr.timestamp), 0) AS last_timestamp,
+        (pdi.amount - COALESCE(SUM(pdr.amount), 0)) AS remaining,
+        pdi.principal,
+        pdi.annual_rate,
+        pdi.term_months,
+        pdi.method,
+        pdi.start_timestamp
+    FROM
+        pixiu_debt_info pdi
+    LEFT JOIN
+        pixiu_debt_repayment pdr
+        ON pdr.debt_id = pdi.id";
+
+const DEBT_INFO_GROUP_BY: &str = " GROUP BY pdi.id";
+
+pub async fn get_debt_info(pool: &Pool<Db>) -> anyhow::Result<Vec<DebtInfo>> {
+    let sql = format!("{DEBT_INFO_SQL}{DEBT_INFO_GROUP_BY}");
+    let rows = sqlx::query_as(&sql).fetch_all(pool).await?;
+    Ok(rows)
+}
+
+pub async fn get_debt(pool: &Pool<Db>, id: u32) -> anyhow::Result<DebtInfo> {
+    let sql = format!("{DEBT_INFO_SQL} WHERE pdi.id = ?{DEBT_INFO_GROUP_BY}");
+    let row: Option<DebtInfo> = sqlx::query_as(&sql).bind(id).fetch_optional(pool).await?;
+    row.ok_or_else(|| StatusError::not_found(format!("debt {id} not found")).into())
+}
+
+/// 生成贷款的还款计划，传入 `prepay` 时一并计算提前还款的影响
+pub async fn get_debt_schedule(
+    pool: &Pool<Db>,
+    id: u32,
+    prepay: Option<(Decimal, i64, loan::PrepayKeep)>,
+) -> anyhow::Result<DebtSchedule> {
+    let debt = get_debt(pool, id).await?;
+    let Some(loan) = debt.loan() else {
+        return Err(StatusError::bad_request(format!("debt {id} has no loan terms")).into());
+    };
+    Ok(DebtSchedule {
+        schedule: loan.schedule()?,
+        prepayment: prepay
+            .map(|(amount, timestamp, keep)| loan.prepay(amount, timestamp, keep))
+            .transpose()?,
+    })
+}
+
+pub async fn insert_debt_info(pool: &Pool<Db>, info: DebtInfo) -> anyhow::Result<u64> {
+    info.validate()?;
+    let sql = "INSERT INTO pixiu_debt_info
+        (name, amount, principal, annual_rate, term_months, method, start_timestamp)
+        VALUES (?, ?, ?, ?, ?, ?, ?)";
+    let result = sqlx::query(sql)
+        .bind(info.name)
+        .bind(Money(info.amount))
+        .bind(info.principal.map(Money))
+        .bind(info.annual_rate)
+        .bind(info.term_months)
+        .bind(info.method)
+        .bind(info.start_timestamp)
+        .execute(pool)
+        .await?;
+    Ok(result.insert_id())
+}
+
+pub async fn update_debt_info(pool: &Pool<Db>, id: u32, info: DebtInfo) -> anyhow::Result<()> {
+    info.validate()?;
+    let sql = "UPDATE pixiu_debt_info SET name = ?, amount = ?, principal = ?, annual_rate = ?,
+        term_months = ?, method = ?, start_timestamp = ? WHERE id = ?";
+    let result = sqlx::query(sql)
+        .bind(info.name)
+        .bind(Money(info.amount))
+        .bind(info.principal.map(Money))
+        .bind(info.annual_rate)
+        .bind(info.term_months)
+        .bind(info.method)
+        .bind(info.start_timestamp)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(StatusError::not_found(format!("debt {id} not found")).into());
     }
-    if let Some(name) = name {
-        if !name.is_empty() {
-            sql.push_str(&format!(" AND name LIKE '%{}%'", name));
-        }
+    Ok(())
+}
+
+/// 删除欠款，还款记录随外键级联删除
+pub async fn delete_debt_info(pool: &Pool<Db>, id: u32) -> anyhow::Result<()> {
+    let sql = "DELETE FROM pixiu_debt_info WHERE id = ?";
+    let result = sqlx::query(sql).bind(id).execute(pool).await?;
+    if result.rows_affected() == 0 {
+        return Err(StatusError::not_found(format!("debt {id} not found")).into());
     }
-    let count: i32 = sqlx::query_scalar(&sql).fetch_one(pool).await?;
-    Ok(count)
+    Ok(())
 }
 
-pub async fn get_debt_info(pool: &MySqlPool) -> anyhow::Result<Vec<DebtInfo>> {
-    let sql = "SELECT * from pixiu_debt_info";
-    let rows = sqlx::query_as(sql).fetch_all(pool).await?;
+pub async fn get_debt_repayments(
+    pool: &Pool<Db>,
+    debt_id: u32,
+) -> anyhow::Result<Vec<DebtRepayment>> {
+    let sql = "SELECT * FROM pixiu_debt_repayment WHERE debt_id = ? ORDER BY timestamp DESC, id";
+    let rows = sqlx::query_as(sql).bind(debt_id).fetch_all(pool).await?;
     Ok(rows)
 }
 
-pub async fn get_property_info(pool: &MySqlPool) -> anyhow::Result<Vec<PropertyInfo>> {
+/// 新增还款记录，欠款不存在时返回 404
+pub async fn insert_debt_repayment(
+    pool: &Pool<Db>,
+    debt_id: u32,
+    info: DebtRepayment,
+) -> anyhow::Result<u64> {
+    let sql = "SELECT id FROM pixiu_debt_info WHERE id = ?";
+    let debt: Option<u32> = sqlx::query_scalar(sql)
+        .bind(debt_id)
+        .fetch_optional(pool)
+        .await?;
+    if debt.is_none() {
+        return Err(StatusError::not_found(format!("debt {debt_id} not found")).into());
+    }
+    let sql = "INSERT INTO pixiu_debt_repayment (debt_id, amount, timestamp) VALUES (?, ?, ?)";
+    let result = sqlx::query(sql)
+        .bind(debt_id)
+        .bind(Money(info.amount))
+        .bind(info.timestamp)
+        .execute(pool)
+        .await?;
+    Ok(result.insert_id())
+}
+
+pub async fn delete_debt_repayment(pool: &Pool<Db>, debt_id: u32, id: u32) -> anyhow::Result<()> {
+    let sql = "DELETE FROM pixiu_debt_repayment WHERE id = ? AND debt_id = ?";
+    let result = sqlx::query(sql)
+        .bind(id)
+        .bind(debt_id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        let message = format!("repayment {id} of debt {debt_id} not found");
+        return Err(StatusError::not_found(message).into());
+    }
+    Ok(())
+}
+
+/// 获取资产账户及当前余额，`archived` 为 false 时不含已归档账户
+///
+/// 余额 = 期初余额 + 期初之后的资金记录 + 转入 - 转出（含手续费），以账户币种计，
+/// 币种与账户不同的资金记录按记录时的汇率折合，`base_amount` 按最新汇率折合为 `base` 币种
+pub async fn get_property_info(
+    pool: &Pool<Db>,
+    archived: bool,
+    base: &str,
+) -> anyhow::Result<Vec<PropertyInfo>> {
     let sql = "SELECT
         ppi.id,
         ppi.name,
-        (ppi.amount + COALESCE(SUM(pfi.amount), 0)) AS amount
+        (ppi.opening_balance
+            + COALESCE((SELECT SUM(pfi.amount) FROM pixiu_fund_info pfi
+                WHERE pfi.source = ppi.name AND pfi.currency = ppi.currency
+                    AND pfi.timestamp > ppi.opening_timestamp AND pfi.deleted_at IS NULL), 0)
+            + COALESCE((SELECT SUM(COALESCE(pt.to_amount, pt.amount)) FROM pixiu_transfer pt
+                WHERE pt.to_source = ppi.name AND pt.timestamp > ppi.opening_timestamp), 0)
+            - COALESCE((SELECT SUM(pt.amount + pt.fee) FROM pixiu_transfer pt
+                WHERE pt.from_source = ppi.name AND pt.timestamp > ppi.opening_timestamp), 0)
+        ) AS amount,
+        ppi.opening_balance,
+        ppi.opening_timestamp,
+        ppi.archived,
+        ppi.currency
     FROM
         pixiu_property_info ppi
-    LEFT JOIN
-        pixiu_fund_info pfi
-        ON pfi.source = ppi.name
-    GROUP BY
-        ppi.id, ppi.name, ppi.amount";
-    let rows = sqlx::query_as(sql).fetch_all(pool).await?;
+    WHERE
+        ? OR NOT ppi.archived";
+    let mut conn = pool.acquire().await?;
+    let mut rows: Vec<PropertyInfo> = sqlx::query_as(sql)
+        .bind(archived)
+        .fetch_all(&mut *conn)
+        .await?;
+    let foreign = currency::foreign_fund_sums(&mut conn, None, i64::MAX).await?;
+    let rates = currency::Rates::load(&mut *conn).await?;
+    for row in rows.iter_mut() {
+        row.amount += foreign.get(&row.name).copied().unwrap_or_default();
+        row.base_amount = rates.convert(row.amount, &row.currency, base, i64::MAX)?;
+    }
     Ok(rows)
 }
 
-pub async fn get_fund_sources(pool: &MySqlPool) -> anyhow::Result<Vec<String>> {
-    let sql = "SELECT DISTINCT source FROM pixiu_fund_info";
+pub async fn insert_property_info(pool: &Pool<Db>, info: PropertyInfo) -> anyhow::Result<u64> {
+    let sql = "INSERT INTO pixiu_property_info (name, opening_balance, opening_timestamp, currency)
+        VALUES (?, ?, ?, ?)";
+    let result = sqlx::query(sql)
+        .bind(info.name)
+        .bind(Money(info.opening_balance))
+        .bind(info.opening_timestamp)
+        .bind(currency::normalize(&info.currency)?)
+        .execute(pool)
+        .await?;
+    Ok(result.insert_id())
+}
+
+/// 更新资产账户，改名时同步修改资金记录和转账的来源，资金记录的修改记入审计日志
+pub async fn update_property_info(
+    pool: &Pool<Db>,
+    id: u32,
+    info: PropertyInfo,
+    client: &str,
+) -> anyhow::Result<()> {
+    let currency = currency::normalize(&info.currency)?;
+    let mut tx = pool.begin().await?;
+    let sql = format!(
+        "SELECT name, opening_balance, opening_timestamp FROM pixiu_property_info
+        WHERE id = ?{}",
+        DIALECT.for_update()
+    );
+    let old: Option<(String, Money, i64)> = sqlx::query_as(&sql)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some((old_name, Money(opening_balance), opening_timestamp)) = old else {
+        return Err(StatusError::not_found(format!("property {id} not found")).into());
+    };
+    // 期初余额或期初时间变化会改变已对账时段的余额
+    if opening_balance != info.opening_balance || opening_timestamp != info.opening_timestamp {
+        let from = opening_timestamp.min(info.opening_timestamp);
+        reconcile::check_open(&mut tx, &old_name, from).await?;
+    }
+    if old_name != info.name {
+        let sql = "SELECT id FROM pixiu_fund_info WHERE source = ? AND deleted_at IS NULL";
+        let fund_ids: Vec<u32> = sqlx::query_scalar(sql)
+            .bind(&old_name)
+            .fetch_all(&mut *tx)
+            .await?;
+        let mut olds = Vec::with_capacity(fund_ids.len());
+        for fund_id in fund_ids {
+            olds.push((fund_id, get_fund_for_update(&mut tx, fund_id).await?));
+        }
+        for sql in [
+            "UPDATE pixiu_fund_info SET source = ? WHERE source = ?",
+            "UPDATE pixiu_transfer SET from_source = ? WHERE from_source = ?",
+            "UPDATE pixiu_transfer SET to_source = ? WHERE to_source = ?",
+            "UPDATE pixiu_balance_assertion SET source = ? WHERE source = ?",
+        ] {
+            sqlx::query(sql)
+                .bind(&info.name)
+                .bind(&old_name)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for (fund_id, old) in &olds {
+            audit::record(&mut tx, *fund_id, audit::Action::Update, Some(old), client).await?;
+        }
+    }
+    let sql = "UPDATE pixiu_property_info
+        SET name = ?, opening_balance = ?, opening_timestamp = ?, currency = ? WHERE id = ?";
+    sqlx::query(sql)
+        .bind(info.name)
+        .bind(Money(info.opening_balance))
+        .bind(info.opening_timestamp)
+        .bind(currency)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// 归档或恢复资产账户，资金记录保持不变
+pub async fn archive_property_info(pool: &Pool<Db>, id: u32, archived: bool) -> anyhow::Result<()> {
+    let sql = "UPDATE pixiu_property_info SET archived = ? WHERE id = ?";
+    let result = sqlx::query(sql)
+        .bind(archived)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(StatusError::not_found(format!("property {id} not found")).into());
+    }
+    Ok(())
+}
+
+pub async fn get_fund_sources(pool: &Pool<Db>) -> anyhow::Result<Vec<String>> {
+    let sql = "SELECT DISTINCT source FROM pixiu_fund_info WHERE deleted_at IS NULL";
     let rows = sqlx::query_scalar(sql).fetch_all(pool).await?;
     Ok(rows)
 }
 
-pub async fn get_fund_types(pool: &MySqlPool) -> anyhow::Result<Vec<String>> {
-    let sql = "SELECT DISTINCT class FROM pixiu_fund_info";
+pub async fn get_fund_types(pool: &Pool<Db>) -> anyhow::Result<Vec<String>> {
+    let sql = "SELECT name FROM pixiu_category
+        UNION SELECT DISTINCT class FROM pixiu_fund_info WHERE deleted_at IS NULL";
     let rows = sqlx::query_scalar(sql).fetch_all(pool).await?;
     Ok(rows)
 }
 
-pub async fn delete_fund_info(pool: &MySqlPool, id: u32) -> anyhow::Result<()> {
-    let sql = "DELETE FROM pixiu_fund_info WHERE id = ?";
-    sqlx::query(sql).bind(id).execute(pool).await?;
+/// 事务中读取未删除的资金记录，不存在时返回 404
+async fn get_fund_for_update(tx: &mut Transaction<'_, Db>, id: u32) -> anyhow::Result<FundInfo> {
+    let fund = audit::get_fund(tx, id).await?;
+    fund.ok_or_else(|| StatusError::not_found(format!("fund {id} not found")).into())
+}
+
+/// 删除资金记录，只标记删除时间，可以恢复，标签和附件保留
+pub async fn delete_fund_info(pool: &Pool<Db>, id: u32, client: &str) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    delete_fund(&mut tx, id, client).await?;
+    tx.commit().await?;
     Ok(())
 }
 
-pub async fn update_fund_info(pool: &MySqlPool, id: u32, info: FundInfo) -> anyhow::Result<()> {
-    let sql = "UPDATE pixiu_fund_info SET amount = ?, name = ?, class = ?, timestamp = ?, source = ? WHERE id = ?";
+async fn delete_fund(tx: &mut Transaction<'_, Db>, id: u32, client: &str) -> anyhow::Result<()> {
+    let old = get_fund_for_update(tx, id).await?;
+    reconcile::check_unlocked(&old)?;
+    let sql = "UPDATE pixiu_fund_info SET deleted_at = ? WHERE id = ?";
     sqlx::query(sql)
-        .bind(info.amount)
-        .bind(info.name)
-        .bind(info.class)
-        .bind(info.timestamp)
-        .bind(info.source)
+        .bind(chrono::Utc::now().timestamp())
         .bind(id)
-        .execute(pool)
+        .execute(&mut **tx)
         .await?;
+    audit::record(tx, id, audit::Action::Delete, Some(&old), client).await
+}
+
+/// 恢复已删除的资金记录，已对账的时间段内不能恢复
+pub async fn restore_fund_info(pool: &Pool<Db>, id: u32, client: &str) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    restore_fund(&mut tx, id, client).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn restore_fund(tx: &mut Transaction<'_, Db>, id: u32, client: &str) -> anyhow::Result<()> {
+    let sql =
+        "UPDATE pixiu_fund_info SET deleted_at = NULL WHERE id = ? AND deleted_at IS NOT NULL";
+    let result = sqlx::query(sql).bind(id).execute(&mut **tx).await?;
+    if result.rows_affected() == 0 {
+        return Err(StatusError::not_found(format!("deleted fund {id} not found")).into());
+    }
+    let fund = get_fund_for_update(tx, id).await?;
+    reconcile::check_open(tx, &fund.source, fund.timestamp).await?;
+    audit::record(tx, id, audit::Action::Restore, None, client).await
+}
+
+/// 彻底删除 `before` 之前删除的资金记录，附件随外键级联删除后再删除磁盘上的文件，返回删除条数
+pub async fn purge_deleted(pool: &Pool<Db>, before: i64) -> anyhow::Result<u64> {
+    let sql = "SELECT pa.path FROM pixiu_attachment pa
+        JOIN pixiu_fund_info pfi ON pfi.id = pa.fund_id WHERE pfi.deleted_at < ?";
+    let paths: Vec<String> = sqlx::query_scalar(sql).bind(before).fetch_all(pool).await?;
+    let sql = "DELETE FROM pixiu_fund_info WHERE deleted_at < ?";
+    let result = sqlx::query(sql).bind(before).execute(pool).await?;
+    for path in paths {
+        attachment::remove_file(&path).await;
+    }
+    Ok(result.rows_affected())
+}
+
+pub async fn update_fund_info(
+    pool: &Pool<Db>,
+    id: u32,
+    info: FundInfo,
+    client: &str,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    update_fund(&mut tx, id, &info, client).await?;
+    tx.commit().await?;
     Ok(())
 }
+
+async fn update_fund(
+    tx: &mut Transaction<'_, Db>,
+    id: u32,
+    info: &FundInfo,
+    client: &str,
+) -> anyhow::Result<()> {
+    let sql = format!(
+        "UPDATE pixiu_fund_info SET amount = ?, name = ?, class = ?, timestamp = ?, source = ?,
+        currency = {FUND_CURRENCY_SQL} WHERE id = ?"
+    );
+    let old = get_fund_for_update(tx, id).await?;
+    reconcile::check_unlocked(&old)?;
+    reconcile::check_open(tx, &info.source, info.timestamp).await?;
+    sqlx::query(&sql)
+        .bind(Money(info.amount))
+        .bind(&info.name)
+        .bind(&info.class)
+        .bind(info.timestamp)
+        .bind(&info.source)
+        .bind(fund_currency(&info.currency)?)
+        .bind(&info.source)
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+    tag::set_fund_tags(tx, id, &info.tags).await?;
+    split::set_fund_splits(tx, id, info).await?;
+    audit::record(tx, id, audit::Action::Update, Some(&old), client).await
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn where_sql(filter: &FundFilter) -> String {
+        let mut qb = QueryBuilder::new("SELECT * FROM pixiu_fund_info");
+        filter.push_where(&mut qb);
+        qb.into_sql()
+    }
+
+    #[test]
+    fn test_filter_binds_every_value() {
+        let filter = FundFilter::new(
+            1,
+            2,
+            Some("支付宝,O'Bank".to_string()),
+            Some("餐饮,a'); DROP TABLE pixiu_fund_info; --".to_string()),
+            Some("O'Brien".to_string()),
+            None,
+        );
+        let expected = format!(
+            "SELECT * FROM pixiu_fund_info WHERE deleted_at IS NULL AND timestamp BETWEEN ? AND ? \
+            AND source IN (?, ?) AND ((class IN (?, ?) OR class IN (WITH RECURSIVE sub (id, name) AS (\
+            SELECT id, name FROM pixiu_category WHERE name IN (?, ?) \
+            UNION ALL SELECT pc.id, pc.name FROM pixiu_category pc \
+            JOIN sub ON pc.parent_id = sub.id) SELECT name FROM sub)) \
+            OR id IN (SELECT fund_id FROM pixiu_fund_split WHERE \
+            (class IN (?, ?) OR class IN (WITH RECURSIVE sub (id, name) AS (\
+            SELECT id, name FROM pixiu_category WHERE name IN (?, ?) \
+            UNION ALL SELECT pc.id, pc.name FROM pixiu_category pc \
+            JOIN sub ON pc.parent_id = sub.id) SELECT name FROM sub)))) AND name LIKE ?{}",
+            DIALECT.like_escape()
+        );
+        assert_eq!(where_sql(&filter), expected);
+        assert_eq!(filter.name_pattern().as_deref(), Some("%O'Brien%"));
+    }
+
+    #[test]
+    fn test_filter_skips_empty_values() {
+        let filter = FundFilter::new(
+            1,
+            2,
+            Some("".to_string()),
+            None,
+            Some("".to_string()),
+            Some("".to_string()),
+        );
+        assert_eq!(
+            where_sql(&filter),
+            "SELECT * FROM pixiu_fund_info WHERE deleted_at IS NULL AND timestamp BETWEEN ? AND ?"
+        );
+        assert_eq!(filter.name_pattern(), None);
+    }
+
+    #[test]
+    fn test_filter_by_tags() {
+        let filter = FundFilter::new(
+            1,
+            2,
+            None,
+            None,
+            None,
+            Some("reimbursable,travel-2026-japan".to_string()),
+        );
+        assert_eq!(
+            where_sql(&filter),
+            "SELECT * FROM pixiu_fund_info WHERE deleted_at IS NULL AND timestamp BETWEEN ? AND ? \
+            AND id IN (SELECT pft.fund_id FROM pixiu_fund_tag pft
+                JOIN pixiu_tag pt ON pt.id = pft.tag_id AND pt.name IN (?, ?))"
+        );
+    }
+
+    #[test]
+    fn test_line_filter_matches_split_class() {
+        let filter = FundFilter::new(1, 2, None, Some("食品".to_string()), None, None);
+        let mut qb = QueryBuilder::new(format!("SELECT * FROM {FUND_LINES_SQL}"));
+        filter.push_line_where(&mut qb);
+        let sql = qb.into_sql();
+        assert!(sql.ends_with(
+            "pixiu_fund_line WHERE deleted_at IS NULL AND timestamp BETWEEN ? AND ? \
+            AND (class IN (?) OR class IN (WITH RECURSIVE sub (id, name) AS (\
+            SELECT id, name FROM pixiu_category WHERE name IN (?) \
+            UNION ALL SELECT pc.id, pc.name FROM pixiu_category pc \
+            JOIN sub ON pc.parent_id = sub.id) SELECT name FROM sub))"
+        ));
+    }
+
+    #[test]
+    fn test_name_wildcards_are_literal() {
+        let filter = FundFilter::new(0, 0, None, None, Some("100%_off\\".to_string()), None);
+        assert_eq!(filter.name_pattern().as_deref(), Some("%100\\%\\_off\\\\%"));
+    }
+
+    #[test]
+    fn test_summary_splits_income_and_expenses() {
+        let rows = vec![
+            (
+                "工资".to_string(),
+                "CNY".to_string(),
+                dec!(8000),
+                dec!(0),
+                0,
+            ),
+            (
+                "餐饮".to_string(),
+                "CNY".to_string(),
+                dec!(20),
+                dec!(-120.50),
+                0,
+            ),
+            ("交通".to_string(), "CNY".to_string(), dec!(0), dec!(-30), 0),
+        ];
+        let summary = summarize(rows, &currency::Rates::default(), "CNY").unwrap();
+        assert_eq!(summary.income, dec!(8020));
+        assert_eq!(summary.expenses, dec!(-150.50));
+        // 净收入的分类不计入支出统计
+        let sums: Vec<_> = summary
+            .sum
+            .iter()
+            .map(|sum| (&*sum.name, sum.value))
+            .collect();
+        assert_eq!(sums, vec![("餐饮", dec!(100.50)), ("交通", dec!(30))]);
+    }
+
+    #[test]
+    fn test_sum_of_small_amounts_is_exact() {
+        // 一万笔一分钱，浮点累加会偏离 100
+        let rows = (0..10_000)
+            .map(|_| ("零食".to_string(), "CNY".to_string(), dec!(0.01), 0))
+            .collect();
+        let sums = sum_by_name(rows, &currency::Rates::default(), "CNY").unwrap();
+        assert_eq!(sums[0].value, dec!(100.00));
+
+        let drift: f32 = (0..10_000).map(|_| 0.01f32).sum();
+        assert_ne!(drift, 100.0);
+    }
+
+    #[test]
+    fn test_sum_by_name_merges_currencies() {
+        let rates = currency::Rates::from([("USD", dec!(7.1))]);
+        let rows = vec![
+            ("餐饮".to_string(), "CNY".to_string(), dec!(10.10), 0),
+            (
+                "餐饮".to_string(),
+                "USD".to_string(),
+                dec!(1.10),
+                1704011400,
+            ),
+            ("工资".to_string(), "CNY".to_string(), dec!(-5000), 0),
+        ];
+        let sums = sum_by_name(rows, &rates, "CNY").unwrap();
+        assert_eq!(sums.len(), 1);
+        assert_eq!(sums[0].value, dec!(17.91));
+    }
+
+    #[test]
+    fn test_amount_json_round_trip() {
+        let json = r#"{"id":null,"amount":-0.1,"name":"a","class":"b","timestamp":0,"source":"c"}"#;
+        let fund: FundInfo = serde_json::from_str(json).unwrap();
+        assert_eq!(fund.amount, dec!(-0.1));
+        assert!(serde_json::to_string(&fund)
+            .unwrap()
+            .contains(r#""amount":-0.1,"#));
+    }
+}
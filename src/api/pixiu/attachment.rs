@@ -0,0 +1,246 @@
+use std::path::PathBuf;
+
+use log::warn;
+use sqlx::Pool;
+
+use super::Db;
+use crate::{api::error::StatusError, storage::InsertId};
+
+/// 单个附件的大小上限
+pub const MAX_SIZE: usize = 20 * 1024 * 1024;
+
+/// 资金记录的附件，文件保存在 `PIXIU_ATTACHMENT_DIR` 目录下
+#[derive(sqlx::FromRow, Debug, serde::Serialize)]
+pub struct Attachment {
+    id: u32,
+    fund_id: u32,
+    file_name: String,
+    content_type: String,
+    size: u64,
+    timestamp: i64,
+    /// 磁盘上的文件名，与上传时的文件名无关
+    #[serde(skip)]
+    path: String,
+}
+
+/// 上传的文件，类型按内容判断，不信任客户端声明的类型
+pub struct Upload {
+    pub file_name: String,
+    pub bytes: Vec<u8>,
+}
+
+fn storage_dir() -> PathBuf {
+    std::env::var("PIXIU_ATTACHMENT_DIR")
+        .unwrap_or_else(|_| "attachments".to_string())
+        .into()
+}
+
+/// 按文件头判断类型，只接受常见位图、PDF 和 OFD（电子发票，ZIP 格式），不接受 SVG 等可含脚本的类型
+fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: [(&[u8], &str); 6] = [
+        (b"\xFF\xD8\xFF", "image/jpeg"),
+        (b"\x89PNG\r\n\x1A\n", "image/png"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/ofd"),
+    ];
+    if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| bytes.starts_with(signature))
+        .map(|(_, content_type)| *content_type)
+}
+
+/// 浏览器可直接显示且不会执行脚本的类型
+fn is_raster(content_type: &str) -> bool {
+    ["image/jpeg", "image/png", "image/gif", "image/webp"].contains(&content_type)
+}
+
+/// 磁盘上的文件名，只保留原文件名中由字母数字组成的扩展名
+fn stored_name(fund_id: u32, nanos: i64, file_name: &str) -> String {
+    let extension = file_name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .filter(|extension| {
+            !extension.is_empty()
+                && extension.len() <= 8
+                && extension.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+    match extension {
+        Some(extension) => format!("{fund_id}-{nanos}.{extension}"),
+        None => format!("{fund_id}-{nanos}"),
+    }
+}
+
+impl Attachment {
+    /// 下载时的 `Content-Disposition`，文件名按 RFC 5987 编码以支持中文，
+    /// 位图在浏览器中直接显示，其余类型一律下载
+    pub fn content_disposition(&self) -> String {
+        let mut encoded = String::new();
+        for byte in self.file_name.bytes() {
+            if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+                encoded.push(byte as char);
+            } else {
+                encoded.push_str(&format!("%{byte:02X}"));
+            }
+        }
+        let disposition = match is_raster(&self.content_type) {
+            true => "inline",
+            false => "attachment",
+        };
+        format!("{disposition}; filename*=UTF-8''{encoded}")
+    }
+
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+}
+
+pub async fn get_attachments(pool: &Pool<Db>, fund_id: u32) -> anyhow::Result<Vec<Attachment>> {
+    let sql = "SELECT * FROM pixiu_attachment WHERE fund_id = ? ORDER BY id";
+    let rows = sqlx::query_as(sql).bind(fund_id).fetch_all(pool).await?;
+    Ok(rows)
+}
+
+async fn get_attachment(pool: &Pool<Db>, id: u32) -> anyhow::Result<Attachment> {
+    let sql = "SELECT * FROM pixiu_attachment WHERE id = ?";
+    let row = sqlx::query_as(sql).bind(id).fetch_optional(pool).await?;
+    row.ok_or_else(|| StatusError::not_found(format!("attachment {id} not found")).into())
+}
+
+/// 保存上传的附件，返回新附件的 id
+pub async fn insert_attachments(
+    pool: &Pool<Db>,
+    fund_id: u32,
+    uploads: Vec<Upload>,
+) -> anyhow::Result<Vec<u64>> {
+    let sql = "SELECT COUNT(*) FROM pixiu_fund_info WHERE id = ? AND deleted_at IS NULL";
+    let count: i64 = sqlx::query_scalar(sql)
+        .bind(fund_id)
+        .fetch_one(pool)
+        .await?;
+    if count == 0 {
+        return Err(StatusError::not_found(format!("fund {fund_id} not found")).into());
+    }
+    let mut content_types = Vec::with_capacity(uploads.len());
+    for upload in &uploads {
+        let Some(content_type) = sniff(&upload.bytes) else {
+            let message = format!("unsupported file type: {}", upload.file_name);
+            return Err(StatusError::bad_request(message).into());
+        };
+        if upload.bytes.len() > MAX_SIZE {
+            let message = format!("{} is too large", upload.file_name);
+            return Err(StatusError::bad_request(message).into());
+        }
+        content_types.push(content_type);
+    }
+
+    let dir = storage_dir();
+    tokio::fs::create_dir_all(&dir).await?;
+    let mut ids = vec![];
+    for (upload, content_type) in uploads.into_iter().zip(content_types) {
+        let now = chrono::Utc::now();
+        let path = stored_name(
+            fund_id,
+            now.timestamp_nanos_opt().unwrap_or_default(),
+            &upload.file_name,
+        );
+        tokio::fs::write(dir.join(&path), &upload.bytes).await?;
+        let sql = "INSERT INTO pixiu_attachment
+            (fund_id, file_name, content_type, size, timestamp, path) VALUES (?, ?, ?, ?, ?, ?)";
+        let result = sqlx::query(sql)
+            .bind(fund_id)
+            .bind(&upload.file_name)
+            .bind(content_type)
+            .bind(upload.bytes.len() as i64)
+            .bind(now.timestamp())
+            .bind(&path)
+            .execute(pool)
+            .await;
+        match result {
+            Ok(result) => ids.push(result.insert_id()),
+            Err(err) => {
+                remove_file(&path).await;
+                return Err(err.into());
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// 读取附件内容，文件已不在磁盘上时返回 404
+pub async fn read_attachment(pool: &Pool<Db>, id: u32) -> anyhow::Result<(Attachment, Vec<u8>)> {
+    let attachment = get_attachment(pool, id).await?;
+    match tokio::fs::read(storage_dir().join(&attachment.path)).await {
+        Ok(bytes) => Ok((attachment, bytes)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            warn!("attachment {id} is missing on disk: {}", attachment.path);
+            Err(StatusError::not_found(format!("attachment {id} not found")).into())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn delete_attachment(pool: &Pool<Db>, id: u32) -> anyhow::Result<()> {
+    let attachment = get_attachment(pool, id).await?;
+    let sql = "DELETE FROM pixiu_attachment WHERE id = ?";
+    sqlx::query(sql).bind(id).execute(pool).await?;
+    remove_file(&attachment.path).await;
+    Ok(())
+}
+
+/// 删除磁盘上的文件，记录已删除，失败只记日志
+pub(super) async fn remove_file(path: &str) {
+    let path = storage_dir().join(path);
+    if let Err(err) = tokio::fs::remove_file(&path).await {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            warn!("remove attachment {} failed: {err}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_name_ignores_user_path() {
+        assert_eq!(stored_name(1, 42, "发票.PDF"), "1-42.pdf");
+        assert_eq!(stored_name(1, 42, "../../etc/passwd"), "1-42");
+        assert_eq!(stored_name(1, 42, "a.b/../../x"), "1-42");
+        assert_eq!(stored_name(1, 42, "receipt"), "1-42");
+    }
+
+    #[test]
+    fn test_content_disposition_encodes_name() {
+        let mut attachment = Attachment {
+            id: 1,
+            fund_id: 1,
+            file_name: "发票 1.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            size: 0,
+            timestamp: 0,
+            path: String::new(),
+        };
+        assert_eq!(
+            attachment.content_disposition(),
+            "attachment; filename*=UTF-8''%E5%8F%91%E7%A5%A8%201.pdf"
+        );
+        attachment.content_type = "image/png".to_string();
+        assert!(attachment.content_disposition().starts_with("inline;"));
+    }
+
+    #[test]
+    fn test_sniff_types() {
+        assert_eq!(sniff(b"\xFF\xD8\xFF\xE0\0\x10JFIF"), Some("image/jpeg"));
+        assert_eq!(sniff(b"%PDF-1.7\n"), Some("application/pdf"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        // 类型以内容为准，SVG 和 HTML 不接受
+        assert_eq!(sniff(b"<svg xmlns=\"http://www.w3.org/2000/svg\">"), None);
+        assert_eq!(sniff(b"<html><script>"), None);
+        assert_eq!(sniff(b""), None);
+    }
+}
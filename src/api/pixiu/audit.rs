@@ -0,0 +1,213 @@
+use sqlx::{types::Json, Pool, Transaction};
+
+use super::{delete_fund, restore_fund, split, update_fund, Db, FundInfo, DIALECT};
+use crate::api::error::StatusError;
+
+/// 资金记录的变更类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Insert,
+    Update,
+    Delete,
+    Restore,
+}
+
+impl Action {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Action::Insert => "insert",
+            Action::Update => "update",
+            Action::Delete => "delete",
+            Action::Restore => "restore",
+        }
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "insert" => Ok(Action::Insert),
+            "update" => Ok(Action::Update),
+            "delete" => Ok(Action::Delete),
+            "restore" => Ok(Action::Restore),
+            _ => anyhow::bail!("unknown audit action: {value}"),
+        }
+    }
+}
+
+/// 一次变更，`old_value`、`new_value` 为变更前后的记录（含标签、拆分）
+#[derive(sqlx::FromRow, Debug, serde::Serialize)]
+pub struct AuditEntry {
+    id: u32,
+    fund_id: u32,
+    #[sqlx(try_from = "String")]
+    action: Action,
+    #[sqlx(json(nullable))]
+    old_value: Option<FundInfo>,
+    #[sqlx(json(nullable))]
+    new_value: Option<FundInfo>,
+    timestamp: i64,
+    /// 发起变更的客户端，接口调用时为 User-Agent
+    client: String,
+}
+
+/// 事务中读取并锁定未删除的资金记录及其标签、拆分
+pub(super) async fn get_fund(
+    tx: &mut Transaction<'_, Db>,
+    id: u32,
+) -> anyhow::Result<Option<FundInfo>> {
+    let sql = format!(
+        "SELECT * FROM pixiu_fund_info WHERE id = ? AND deleted_at IS NULL{}",
+        DIALECT.for_update()
+    );
+    let fund: Option<FundInfo> = sqlx::query_as(&sql)
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    let Some(mut fund) = fund else {
+        return Ok(None);
+    };
+    let sql = "SELECT pt.name FROM pixiu_fund_tag pft
+        JOIN pixiu_tag pt ON pt.id = pft.tag_id WHERE pft.fund_id = ? ORDER BY pt.name";
+    fund.tags = sqlx::query_scalar(sql)
+        .bind(id)
+        .fetch_all(&mut **tx)
+        .await?;
+    fund.splits = split::get_fund_splits(tx, id).await?;
+    Ok(Some(fund))
+}
+
+/// 在变更所在的事务中记录审计日志，变更后的值从数据库读取
+pub(super) async fn record(
+    tx: &mut Transaction<'_, Db>,
+    fund_id: u32,
+    action: Action,
+    old: Option<&FundInfo>,
+    client: &str,
+) -> anyhow::Result<()> {
+    let new = get_fund(tx, fund_id).await?;
+    let sql = "INSERT INTO pixiu_fund_audit
+        (fund_id, action, old_value, new_value, timestamp, client) VALUES (?, ?, ?, ?, ?, ?)";
+    sqlx::query(sql)
+        .bind(fund_id)
+        .bind(action.as_str())
+        .bind(old.map(Json))
+        .bind(new.as_ref().map(Json))
+        .bind(chrono::Utc::now().timestamp())
+        .bind(client.chars().take(255).collect::<String>())
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// 最近的变更，可只看某条资金记录
+pub async fn get_audits(
+    pool: &Pool<Db>,
+    fund_id: Option<u32>,
+    limit: u32,
+) -> anyhow::Result<Vec<AuditEntry>> {
+    let sql = "SELECT * FROM pixiu_fund_audit WHERE ? IS NULL OR fund_id = ?
+        ORDER BY id DESC LIMIT ?";
+    let rows = sqlx::query_as(sql)
+        .bind(fund_id)
+        .bind(fund_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows)
+}
+
+/// 撤销一次变更：新增的删除，修改的改回原值，删除的恢复，恢复的再删除
+///
+/// 只能撤销资金记录最近的一次变更，撤销本身也会记录为一次变更
+pub async fn revert(pool: &Pool<Db>, id: u32, client: &str) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    let sql = "SELECT * FROM pixiu_fund_audit WHERE id = ?";
+    let entry: Option<AuditEntry> = sqlx::query_as(sql)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some(entry) = entry else {
+        return Err(StatusError::not_found(format!("audit {id} not found")).into());
+    };
+    // 锁定资金记录（含已删除的），其他变更要等撤销完成
+    let sql = format!(
+        "SELECT id FROM pixiu_fund_info WHERE id = ?{}",
+        DIALECT.for_update()
+    );
+    sqlx::query(&sql)
+        .bind(entry.fund_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let sql = "SELECT MAX(id) FROM pixiu_fund_audit WHERE fund_id = ?";
+    let latest: Option<u32> = sqlx::query_scalar(sql)
+        .bind(entry.fund_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    check_latest(&entry, latest)?;
+    match (entry.action, entry.old_value) {
+        (Action::Insert | Action::Restore, _) => {
+            delete_fund(&mut tx, entry.fund_id, client).await?
+        }
+        (Action::Update, Some(old)) => update_fund(&mut tx, entry.fund_id, &old, client).await?,
+        (Action::Update, None) => {
+            return Err(StatusError::bad_request("nothing to revert").into());
+        }
+        (Action::Delete, _) => restore_fund(&mut tx, entry.fund_id, client).await?,
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// 之后还有变更时撤销会覆盖那些变更
+fn check_latest(entry: &AuditEntry, latest: Option<u32>) -> anyhow::Result<()> {
+    if latest != Some(entry.id) {
+        let message = format!(
+            "audit {} is not the latest change of fund {}",
+            entry.id, entry.fund_id
+        );
+        return Err(StatusError::conflict(message).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_round_trip() {
+        for action in [
+            Action::Insert,
+            Action::Update,
+            Action::Delete,
+            Action::Restore,
+        ] {
+            assert_eq!(
+                Action::try_from(action.as_str().to_string()).unwrap(),
+                action
+            );
+        }
+        assert!(Action::try_from("drop".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_only_latest_change_is_reverted() {
+        let entry = AuditEntry {
+            id: 3,
+            fund_id: 1,
+            action: Action::Update,
+            old_value: None,
+            new_value: None,
+            timestamp: 0,
+            client: String::new(),
+        };
+        assert!(check_latest(&entry, Some(3)).is_ok());
+        let err = check_latest(&entry, Some(4)).unwrap_err();
+        let StatusError(status, _) = err.downcast_ref::<StatusError>().unwrap();
+        assert_eq!(*status, axum::http::StatusCode::CONFLICT);
+    }
+}
@@ -0,0 +1,118 @@
+use sqlx::Pool;
+
+use super::{budget, delete_fund, insert_fund, rule, update_fund, Db, FundInfo};
+use crate::api::error::StatusError;
+
+/// 单次批量操作的条数上限
+const MAX_OPERATIONS: usize = 1000;
+
+/// 批量操作中的一项
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    /// 新增，与单条新增一样先应用自动分类规则
+    Create {
+        fund: FundInfo,
+    },
+    Update {
+        id: u32,
+        fund: FundInfo,
+    },
+    Delete {
+        id: u32,
+    },
+}
+
+/// 一项操作的结果，成功时 `id` 为记录 id，失败时 `error` 为原因
+#[derive(Debug, serde::Serialize)]
+pub struct OperationResult {
+    id: Option<u32>,
+    error: Option<String>,
+}
+
+/// `committed` 为 false 时全部操作均已回滚
+#[derive(Debug, serde::Serialize)]
+pub struct BatchResult {
+    committed: bool,
+    results: Vec<OperationResult>,
+}
+
+/// 在一个事务中依次执行，任一项参数错误或记录不存在时整体回滚
+pub async fn execute(
+    pool: &Pool<Db>,
+    operations: Vec<Operation>,
+    client: &str,
+) -> anyhow::Result<BatchResult> {
+    if operations.len() > MAX_OPERATIONS {
+        let message = format!("at most {MAX_OPERATIONS} operations per batch");
+        return Err(StatusError::bad_request(message).into());
+    }
+    let rules = rule::Rules::load(pool).await?;
+    let total = operations.len();
+    let mut results = Vec::with_capacity(total);
+    let mut created = vec![];
+    let mut tx = pool.begin().await?;
+    for operation in operations {
+        let result = match operation {
+            Operation::Create { mut fund } => {
+                rules.apply(&mut fund);
+                let result = insert_fund(&mut tx, &fund, client).await;
+                created.push(fund);
+                result
+            }
+            Operation::Update { id, fund } => {
+                update_fund(&mut tx, id, &fund, client).await.map(|_| id)
+            }
+            Operation::Delete { id } => delete_fund(&mut tx, id, client).await.map(|_| id),
+        };
+        match result {
+            Ok(id) => results.push(OperationResult {
+                id: Some(id),
+                error: None,
+            }),
+            Err(err) => {
+                // 数据库等内部错误直接返回，事务随 `tx` 释放回滚
+                let Some(StatusError(_, message)) = err.downcast_ref::<StatusError>() else {
+                    return Err(err);
+                };
+                results.push(OperationResult {
+                    id: None,
+                    error: Some(message.clone()),
+                });
+                results.resize_with(total, || OperationResult {
+                    id: None,
+                    error: Some("not executed".to_string()),
+                });
+                return Ok(BatchResult {
+                    committed: false,
+                    results,
+                });
+            }
+        }
+    }
+    tx.commit().await?;
+    budget::spawn_notify(pool, created);
+    Ok(BatchResult {
+        committed: true,
+        results,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_operations() {
+        let json = r#"[
+            {"op":"create","fund":{"id":null,"amount":-1.5,"name":"a","class":"b","timestamp":0,"source":"c"}},
+            {"op":"update","id":2,"fund":{"id":2,"amount":3,"name":"a","class":"b","timestamp":0,"source":"c","tags":["x"]}},
+            {"op":"delete","id":3}
+        ]"#;
+        let operations: Vec<Operation> = serde_json::from_str(json).unwrap();
+        assert!(matches!(operations[0], Operation::Create { .. }));
+        assert!(matches!(&operations[1], Operation::Update { id: 2, fund } if fund.tags == ["x"]));
+        assert!(matches!(operations[2], Operation::Delete { id: 3 }));
+        assert!(serde_json::from_str::<Operation>(r#"{"op":"drop","id":1}"#).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{Datelike, Months, NaiveDate, NaiveTime, TimeZone};
+use chrono_tz::Asia::Shanghai;
+use log::warn;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use sqlx::Pool;
+
+use super::{category, currency, get_sum_info, Db, FundFilter, FundInfo};
+use crate::{
+    api::error::StatusError,
+    storage::{InsertId, Money},
+    utils,
+};
+
+/// 发送提醒的预算使用比例
+const THRESHOLDS: [Decimal; 2] = [dec!(0.8), dec!(1.0)];
+/// 一次写入的预算提醒最长耗时，超时后放弃
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 分类的月度预算，包含下级分类的支出，`source` 为空时统计所有来源
+#[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
+pub struct BudgetInfo {
+    id: Option<u32>,
+    class: String,
+    source: Option<String>,
+    #[sqlx(try_from = "Money")]
+    amount: Decimal,
+}
+
+/// 预算在某月的执行情况
+#[derive(Debug, serde::Serialize)]
+pub struct BudgetStatus {
+    #[serde(flatten)]
+    budget: BudgetInfo,
+    spent: Decimal,
+    remaining: Decimal,
+    /// 按已过天数线性推算的月末支出
+    projected: Decimal,
+}
+
+pub async fn insert_budget(pool: &Pool<Db>, info: BudgetInfo) -> anyhow::Result<u64> {
+    let sql = "INSERT INTO pixiu_budget (class, source, amount) VALUES (?, ?, ?)";
+    let result = sqlx::query(sql)
+        .bind(info.class)
+        .bind(info.source.filter(|source| !source.is_empty()))
+        .bind(Money(info.amount))
+        .execute(pool)
+        .await?;
+    Ok(result.insert_id())
+}
+
+pub async fn update_budget(pool: &Pool<Db>, id: u32, info: BudgetInfo) -> anyhow::Result<()> {
+    let sql = "UPDATE pixiu_budget SET class = ?, source = ?, amount = ? WHERE id = ?";
+    sqlx::query(sql)
+        .bind(info.class)
+        .bind(info.source.filter(|source| !source.is_empty()))
+        .bind(Money(info.amount))
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_budget(pool: &Pool<Db>, id: u32) -> anyhow::Result<()> {
+    let sql = "DELETE FROM pixiu_budget WHERE id = ?";
+    sqlx::query(sql).bind(id).execute(pool).await?;
+    Ok(())
+}
+
+/// `timestamp` 所在自然月（上海时区）的起止时间，左闭右开，超出日期范围时返回 400
+fn month_range(timestamp: i64) -> anyhow::Result<(i64, i64)> {
+    let out_of_range =
+        || StatusError::bad_request(format!("timestamp {timestamp} is out of range"));
+    let date = Shanghai
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .ok_or_else(out_of_range)?
+        .date_naive();
+    let first = NaiveDate::from_ymd_opt(date.year(), date.month(), 1).unwrap();
+    let next = first
+        .checked_add_months(Months::new(1))
+        .ok_or_else(out_of_range)?;
+    let start = |date: NaiveDate| {
+        Shanghai
+            .from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .earliest()
+            .map(|time| time.timestamp())
+            .ok_or_else(out_of_range)
+    };
+    Ok((start(first)?, start(next)?))
+}
+
+/// 按已过时间比例推算月末支出，`now` 不在本月时即为实际支出
+fn project(spent: Decimal, (from, to): (i64, i64), now: i64) -> Decimal {
+    if now <= from || now >= to {
+        return spent;
+    }
+    (spent * Decimal::from(to - from) / Decimal::from(now - from)).round_dp(2)
+}
+
+/// 各预算在 `timestamp` 所在月份的支出，统计口径与 `get_sum_info` 相同，上级分类含下级分类
+async fn spent(
+    pool: &Pool<Db>,
+    tree: &category::Tree,
+    budgets: &[BudgetInfo],
+    (from, to): (i64, i64),
+) -> anyhow::Result<Vec<Decimal>> {
+    // 按来源分别汇总，None 表示所有来源
+    let mut sums: HashMap<Option<&str>, Vec<(String, Decimal)>> = HashMap::new();
+    for budget in budgets {
+        let source = budget.source.as_deref();
+        if sums.contains_key(&source) {
+            continue;
+        }
+        let filter = FundFilter {
+            from,
+            to: to - 1,
+            sources: source.map(str::to_string).into_iter().collect(),
+            ..Default::default()
+        };
+        let sum = get_sum_info(pool, &filter, None, currency::BASE_CURRENCY)
+            .await?
+            .into_iter()
+            .map(|sum| (sum.name, sum.value))
+            .collect();
+        sums.insert(source, sum);
+    }
+    Ok(budgets
+        .iter()
+        .map(|budget| {
+            sums[&budget.source.as_deref()]
+                .iter()
+                .filter(|(class, _)| tree.is_within(class, &budget.class))
+                .map(|(_, value)| *value)
+                .sum()
+        })
+        .collect())
+}
+
+/// 获取 `timestamp` 所在月份所有预算的执行情况
+pub async fn get_budget_status(
+    pool: &Pool<Db>,
+    timestamp: i64,
+) -> anyhow::Result<Vec<BudgetStatus>> {
+    let sql = "SELECT * FROM pixiu_budget ORDER BY class, source";
+    let budgets: Vec<BudgetInfo> = sqlx::query_as(sql).fetch_all(pool).await?;
+    let tree = category::Tree::load(pool).await?;
+    let range = month_range(timestamp)?;
+    let spent = spent(pool, &tree, &budgets, range).await?;
+    let now = chrono::Utc::now().timestamp();
+    Ok(budgets
+        .into_iter()
+        .zip(spent)
+        .map(|(budget, spent)| BudgetStatus {
+            remaining: budget.amount - spent,
+            projected: project(spent, range, now),
+            spent,
+            budget,
+        })
+        .collect())
+}
+
+/// 本次支出使预算使用比例越过的最高阈值
+fn crossed_threshold(budget: Decimal, before: Decimal, after: Decimal) -> Option<Decimal> {
+    if budget <= Decimal::ZERO {
+        return None;
+    }
+    THRESHOLDS
+        .into_iter()
+        .rev()
+        .find(|threshold| before < budget * threshold && after >= budget * threshold)
+}
+
+/// 本次支出计入 `class` 的金额，拆分的记录按各拆分的分类计
+fn cost(tree: &category::Tree, fund: &FundInfo, class: &str) -> Decimal {
+    fund.lines()
+        .into_iter()
+        .filter(|(line_class, _)| tree.is_within(line_class, class))
+        .map(|(_, amount)| -amount)
+        .sum()
+}
+
+/// 在后台检查新增资金记录相关的预算，不阻塞写入，失败或超时只记录日志
+pub fn spawn_notify(pool: &Pool<Db>, funds: Vec<FundInfo>) {
+    if funds.is_empty() {
+        return;
+    }
+    let pool = pool.clone();
+    tokio::spawn(async move {
+        let notify_all = async {
+            for fund in &funds {
+                if let Err(err) = notify(&pool, fund).await {
+                    warn!("budget notify failed: {err:#}");
+                }
+            }
+        };
+        if tokio::time::timeout(NOTIFY_TIMEOUT, notify_all)
+            .await
+            .is_err()
+        {
+            warn!("budget notify timed out after {NOTIFY_TIMEOUT:?}");
+        }
+    });
+}
+
+/// 新增资金记录后检查相关预算，越过阈值时发送提醒
+async fn notify(pool: &Pool<Db>, fund: &FundInfo) -> anyhow::Result<()> {
+    if fund
+        .lines()
+        .iter()
+        .all(|(_, amount)| *amount >= Decimal::ZERO)
+    {
+        return Ok(());
+    }
+    let sql = "SELECT * FROM pixiu_budget WHERE source IS NULL OR source = ?";
+    let budgets: Vec<BudgetInfo> = sqlx::query_as(sql)
+        .bind(&fund.source)
+        .fetch_all(pool)
+        .await?;
+    let tree = category::Tree::load(pool).await?;
+    let (budgets, costs): (Vec<BudgetInfo>, Vec<Decimal>) = budgets
+        .into_iter()
+        .map(|budget| {
+            let cost = cost(&tree, fund, &budget.class);
+            (budget, cost)
+        })
+        .filter(|(_, cost)| *cost > Decimal::ZERO)
+        .unzip();
+    if budgets.is_empty() {
+        return Ok(());
+    }
+    let spent = spent(pool, &tree, &budgets, month_range(fund.timestamp)?).await?;
+    for ((budget, cost), after) in budgets.iter().zip(costs).zip(spent) {
+        let Some(threshold) = crossed_threshold(budget.amount, after - cost, after) else {
+            continue;
+        };
+        let state = if threshold >= Decimal::ONE {
+            "已超出预算"
+        } else {
+            "已用去预算八成"
+        };
+        let scope = match &budget.source {
+            Some(source) => format!("{} {}", budget.class, source),
+            None => budget.class.clone(),
+        };
+        let message = format!(
+            "预算提醒 {scope} {} 已支出 {after:.2} 元 预算 {:.2} 元 {state}",
+            utils::timestamp2time(fund.timestamp, "%Y年%m月"),
+            budget.amount,
+        );
+        if let Err(err) = utils::send_message(&message).await {
+            warn!("send budget message failed: {err:#}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_month_range() {
+        // 2024-02-15 12:00:00 +08:00
+        let (from, to) = month_range(1707969600).unwrap();
+        // 2024-02-01 00:00:00 +08:00 .. 2024-03-01 00:00:00 +08:00
+        assert_eq!(from, 1706716800);
+        assert_eq!(to, 1709222400);
+        // 2023-12-31 23:30:00 +08:00 属于 12 月
+        let (from, to) = month_range(1704036600).unwrap();
+        assert_eq!(from, 1701360000);
+        assert_eq!(to, 1704038400);
+        assert!(month_range(i64::MAX).is_err());
+        assert!(month_range(i64::MIN).is_err());
+    }
+
+    #[test]
+    fn test_project() {
+        let range = (0, 30 * 86400);
+        assert_eq!(project(dec!(100), range, 10 * 86400), dec!(300));
+        assert_eq!(project(dec!(100), range, 30 * 86400), dec!(100));
+        assert_eq!(project(dec!(100), range, -1), dec!(100));
+        assert_eq!(project(dec!(100), range, 7 * 86400), dec!(428.57));
+    }
+
+    #[test]
+    fn test_crossed_threshold() {
+        assert_eq!(crossed_threshold(dec!(1000), dec!(700), dec!(799.99)), None);
+        assert_eq!(
+            crossed_threshold(dec!(1000), dec!(700), dec!(800)),
+            Some(dec!(0.8))
+        );
+        assert_eq!(
+            crossed_threshold(dec!(1000), dec!(850), dec!(1000.01)),
+            Some(dec!(1.0))
+        );
+        assert_eq!(
+            crossed_threshold(dec!(1000), dec!(700), dec!(1200)),
+            Some(dec!(1.0))
+        );
+        assert_eq!(crossed_threshold(dec!(1000), dec!(1100), dec!(1200)), None);
+        assert_eq!(crossed_threshold(dec!(0), dec!(0), dec!(10)), None);
+    }
+}
@@ -0,0 +1,282 @@
+use sqlx::{Pool, QueryBuilder};
+
+use super::{audit, get_fund_for_update, reconcile, Db};
+use crate::{api::error::StatusError, storage::InsertId};
+
+/// 分类，资金记录的 `class` 对应分类名称，`parent_id` 为空表示顶级分类
+#[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Category {
+    id: Option<u32>,
+    name: String,
+    parent_id: Option<u32>,
+}
+
+/// 分类树，不在树中的分类视为顶级分类
+#[derive(Debug)]
+pub struct Tree(Vec<Category>);
+
+impl Tree {
+    pub async fn load(pool: &Pool<Db>) -> anyhow::Result<Self> {
+        Ok(Tree(get_categories(pool).await?))
+    }
+
+    fn parent(&self, category: &Category) -> Option<&Category> {
+        let parent_id = category.parent_id?;
+        self.0.iter().find(|parent| parent.id == Some(parent_id))
+    }
+
+    /// 从顶级分类到 `name` 的路径
+    fn path<'a>(&'a self, name: &'a str) -> Vec<&'a str> {
+        let mut path = vec![];
+        let mut current = self.0.iter().find(|category| category.name == name);
+        // 最多走过全部分类，避免脏数据成环时死循环
+        while let Some(category) = current.filter(|_| path.len() <= self.0.len()) {
+            path.push(category.name.as_str());
+            current = self.parent(category);
+        }
+        if path.is_empty() {
+            path.push(name);
+        }
+        path.reverse();
+        path
+    }
+
+    /// `name` 在第 `level` 层（顶级为 1）的上级分类，层级不足时为自身
+    pub fn ancestor(&self, name: &str, level: usize) -> String {
+        let path = self.path(name);
+        path[level.clamp(1, path.len()) - 1].to_string()
+    }
+
+    /// `name` 是否为 `ancestor` 自身或其下级分类
+    pub fn is_within(&self, name: &str, ancestor: &str) -> bool {
+        self.path(name).contains(&ancestor)
+    }
+
+    /// `parent_id` 是否为 `id` 自身或其下级分类
+    fn is_descendant(&self, parent_id: u32, id: u32) -> bool {
+        let mut current = self
+            .0
+            .iter()
+            .find(|category| category.id == Some(parent_id));
+        let mut steps = 0;
+        while let Some(category) = current.filter(|_| steps <= self.0.len()) {
+            if category.id == Some(id) {
+                return true;
+            }
+            current = self.parent(category);
+            steps += 1;
+        }
+        false
+    }
+}
+
+/// 追加 `(class IN (...) OR class IN (下级分类))` 条件，选中上级分类时包含全部下级分类
+pub(super) fn push_class_condition(qb: &mut QueryBuilder<'_, Db>, classes: &[String]) {
+    qb.push("(class IN (");
+    let mut separated = qb.separated(", ");
+    for class in classes {
+        separated.push_bind(class.clone());
+    }
+    qb.push(
+        ") OR class IN (WITH RECURSIVE sub (id, name) AS (\
+        SELECT id, name FROM pixiu_category WHERE name IN (",
+    );
+    let mut separated = qb.separated(", ");
+    for class in classes {
+        separated.push_bind(class.clone());
+    }
+    qb.push(
+        ") UNION ALL SELECT pc.id, pc.name FROM pixiu_category pc \
+        JOIN sub ON pc.parent_id = sub.id) SELECT name FROM sub))",
+    );
+}
+
+pub async fn get_categories(pool: &Pool<Db>) -> anyhow::Result<Vec<Category>> {
+    let sql = "SELECT * FROM pixiu_category ORDER BY name";
+    let rows = sqlx::query_as(sql).fetch_all(pool).await?;
+    Ok(rows)
+}
+
+/// 校验名称和上级分类，上级分类不能是自身或下级分类
+async fn validate(pool: &Pool<Db>, id: Option<u32>, info: &Category) -> anyhow::Result<String> {
+    let name = info.name.trim();
+    if name.is_empty() || name.contains(',') {
+        return Err(StatusError::bad_request(format!("invalid category: {name}")).into());
+    }
+    if let Some(parent_id) = info.parent_id {
+        let tree = Tree::load(pool).await?;
+        if !tree.0.iter().any(|category| category.id == Some(parent_id)) {
+            return Err(StatusError::bad_request(format!("no parent category {parent_id}")).into());
+        }
+        if id.is_some_and(|id| tree.is_descendant(parent_id, id)) {
+            return Err(StatusError::bad_request("category cycle").into());
+        }
+    }
+    Ok(name.to_string())
+}
+
+/// 重名时返回 400
+fn map_duplicate(err: sqlx::Error, name: &str) -> anyhow::Error {
+    match err.as_database_error() {
+        Some(db) if db.is_unique_violation() => {
+            StatusError::bad_request(format!("category {name} already exists")).into()
+        }
+        _ => err.into(),
+    }
+}
+
+pub async fn insert_category(pool: &Pool<Db>, info: Category) -> anyhow::Result<u64> {
+    let name = validate(pool, None, &info).await?;
+    let sql = "INSERT INTO pixiu_category (name, parent_id) VALUES (?, ?)";
+    let result = sqlx::query(sql)
+        .bind(&name)
+        .bind(info.parent_id)
+        .execute(pool)
+        .await
+        .map_err(|err| map_duplicate(err, &name))?;
+    Ok(result.insert_id())
+}
+
+/// 修改分类，重命名时同步修改资金记录、拆分、预算、周期记账和自动分类规则中的分类
+///
+/// 资金记录的修改记入审计日志，涉及已对账的资金记录时返回 409
+pub async fn update_category(
+    pool: &Pool<Db>,
+    id: u32,
+    info: Category,
+    client: &str,
+) -> anyhow::Result<()> {
+    let name = validate(pool, Some(id), &info).await?;
+    let mut tx = pool.begin().await?;
+    let sql = "SELECT name FROM pixiu_category WHERE id = ?";
+    let old: Option<String> = sqlx::query_scalar(sql)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some(old) = old else {
+        return Err(StatusError::not_found(format!("category {id} not found")).into());
+    };
+    let sql = "UPDATE pixiu_category SET name = ?, parent_id = ? WHERE id = ?";
+    sqlx::query(sql)
+        .bind(&name)
+        .bind(info.parent_id)
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| map_duplicate(err, &name))?;
+    if old != name {
+        let sql = "SELECT id FROM pixiu_fund_info WHERE deleted_at IS NULL
+            AND (class = ? OR id IN (SELECT fund_id FROM pixiu_fund_split WHERE class = ?))";
+        let fund_ids: Vec<u32> = sqlx::query_scalar(sql)
+            .bind(&old)
+            .bind(&old)
+            .fetch_all(&mut *tx)
+            .await?;
+        let mut olds = Vec::with_capacity(fund_ids.len());
+        for fund_id in fund_ids {
+            let fund = get_fund_for_update(&mut tx, fund_id).await?;
+            reconcile::check_unlocked(&fund)?;
+            reconcile::check_open(&mut tx, &fund.source, fund.timestamp).await?;
+            olds.push((fund_id, fund));
+        }
+        for (table, column) in [
+            ("pixiu_fund_info", "class"),
+            ("pixiu_fund_split", "class"),
+            ("pixiu_budget", "class"),
+            ("pixiu_recurring", "class"),
+            ("pixiu_rule", "set_class"),
+        ] {
+            let sql = format!("UPDATE {table} SET {column} = ? WHERE {column} = ?");
+            sqlx::query(&sql)
+                .bind(&name)
+                .bind(&old)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for (fund_id, old) in &olds {
+            audit::record(&mut tx, *fund_id, audit::Action::Update, Some(old), client).await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// 删除分类，其下级分类改挂到它的上级，资金记录的分类名称保持不变
+pub async fn delete_category(pool: &Pool<Db>, id: u32) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    let sql = "SELECT parent_id FROM pixiu_category WHERE id = ?";
+    let parent: Option<Option<u32>> = sqlx::query_scalar(sql)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if let Some(parent) = parent {
+        let sql = "UPDATE pixiu_category SET parent_id = ? WHERE parent_id = ?";
+        sqlx::query(sql)
+            .bind(parent)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let sql = "DELETE FROM pixiu_category WHERE id = ?";
+    sqlx::query(sql).bind(id).execute(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(id: u32, name: &str, parent_id: Option<u32>) -> Category {
+        Category {
+            id: Some(id),
+            name: name.to_string(),
+            parent_id,
+        }
+    }
+
+    fn tree() -> Tree {
+        Tree(vec![
+            category(1, "餐饮", None),
+            category(2, "外卖", Some(1)),
+            category(3, "堂食", Some(1)),
+            category(4, "夜宵", Some(2)),
+        ])
+    }
+
+    #[test]
+    fn test_ancestor_rolls_up_to_level() {
+        let tree = tree();
+        assert_eq!(tree.ancestor("夜宵", 1), "餐饮");
+        assert_eq!(tree.ancestor("夜宵", 2), "外卖");
+        assert_eq!(tree.ancestor("夜宵", 5), "夜宵");
+        assert_eq!(tree.ancestor("堂食", 1), "餐饮");
+        // 不在树中的分类视为顶级分类
+        assert_eq!(tree.ancestor("交通", 1), "交通");
+    }
+
+    #[test]
+    fn test_is_within_ancestor() {
+        let tree = tree();
+        assert!(tree.is_within("夜宵", "餐饮"));
+        assert!(tree.is_within("夜宵", "夜宵"));
+        assert!(!tree.is_within("餐饮", "外卖"));
+        assert!(!tree.is_within("堂食", "外卖"));
+        assert!(tree.is_within("交通", "交通"));
+    }
+
+    #[test]
+    fn test_parent_cannot_be_descendant() {
+        let tree = tree();
+        assert!(tree.is_descendant(4, 1));
+        assert!(tree.is_descendant(2, 2));
+        assert!(!tree.is_descendant(1, 2));
+        assert!(!tree.is_descendant(3, 2));
+    }
+
+    #[test]
+    fn test_path_stops_on_cycle() {
+        let tree = Tree(vec![category(1, "a", Some(2)), category(2, "b", Some(1))]);
+        assert!(tree.path("a").len() <= 3);
+    }
+}
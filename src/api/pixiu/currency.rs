@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, TimeZone};
+use chrono_tz::Asia::Shanghai;
+use rust_decimal::Decimal;
+use sqlx::{Database, Executor, Pool, QueryBuilder};
+
+use super::{Db, DIALECT};
+use crate::{api::error::StatusError, storage::Money};
+
+/// 记账本位币，汇率均为一单位外币折合的本位币
+pub const BASE_CURRENCY: &str = "CNY";
+const INSERT_BATCH_SIZE: usize = 1000;
+
+/// 汇率，`timestamp` 为生效时间
+#[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ExchangeRate {
+    id: Option<u32>,
+    currency: String,
+    /// 一单位 `currency` 折合的人民币
+    #[sqlx(try_from = "Money")]
+    rate: Decimal,
+    timestamp: i64,
+}
+
+pub fn default_currency() -> String {
+    BASE_CURRENCY.to_string()
+}
+
+/// 币种代码统一为三位大写字母，如 `USD`
+pub fn normalize(currency: &str) -> anyhow::Result<String> {
+    let code = currency.trim().to_ascii_uppercase();
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(StatusError::bad_request(format!("invalid currency: {currency}")).into());
+    }
+    Ok(code)
+}
+
+/// 统计使用的本位币，未指定时为人民币
+pub fn base(currency: Option<&str>) -> anyhow::Result<String> {
+    currency
+        .filter(|currency| !currency.is_empty())
+        .map_or_else(|| Ok(default_currency()), normalize)
+}
+
+/// 各币种的汇率历史，按生效时间从早到晚排列
+#[derive(Debug, Default)]
+pub struct Rates(HashMap<String, Vec<(i64, Decimal)>>);
+
+impl Rates {
+    pub async fn load<'e>(executor: impl Executor<'e, Database = Db>) -> anyhow::Result<Self> {
+        let sql = "SELECT currency, timestamp, rate FROM pixiu_exchange_rate
+            ORDER BY currency, timestamp";
+        let rows: Vec<(String, i64, Money)> = sqlx::query_as(sql).fetch_all(executor).await?;
+        let mut rates: HashMap<String, Vec<(i64, Decimal)>> = HashMap::new();
+        for (currency, timestamp, Money(rate)) in rows {
+            rates.entry(currency).or_default().push((timestamp, rate));
+        }
+        Ok(Rates(rates))
+    }
+
+    /// `timestamp` 时生效的汇率，早于第一条汇率时取第一条
+    fn rate(&self, currency: &str, timestamp: i64) -> anyhow::Result<Decimal> {
+        if currency == BASE_CURRENCY {
+            return Ok(Decimal::ONE);
+        }
+        let history = self
+            .0
+            .get(currency)
+            .filter(|history| !history.is_empty())
+            .ok_or_else(|| StatusError::bad_request(format!("no exchange rate for {currency}")))?;
+        let index = history.partition_point(|(time, _)| *time <= timestamp);
+        Ok(history[index.saturating_sub(1)].1)
+    }
+
+    /// 按 `timestamp` 时的汇率将 `from` 币种的金额换算为 `to` 币种，结果取整到分
+    pub fn convert(
+        &self,
+        amount: Decimal,
+        from: &str,
+        to: &str,
+        timestamp: i64,
+    ) -> anyhow::Result<Decimal> {
+        if from == to {
+            return Ok(amount);
+        }
+        Ok((amount * self.rate(from, timestamp)? / self.rate(to, timestamp)?).round_dp(2))
+    }
+}
+
+/// 各账户中币种与账户不同的资金记录，按记录时的汇率折合为账户币种后的合计，
+/// 只统计期初之后、`at`（含）之前的记录，可只看某个账户
+pub(super) async fn foreign_fund_sums(
+    conn: &mut <Db as Database>::Connection,
+    source: Option<&str>,
+    at: i64,
+) -> anyhow::Result<HashMap<String, Decimal>> {
+    let sql = "SELECT ppi.name, ppi.currency, pfi.currency, pfi.timestamp, pfi.amount
+        FROM pixiu_fund_info pfi
+        JOIN pixiu_property_info ppi ON ppi.name = pfi.source
+        WHERE pfi.currency <> ppi.currency AND pfi.timestamp > ppi.opening_timestamp
+            AND pfi.timestamp <= ? AND pfi.deleted_at IS NULL AND (? IS NULL OR ppi.name = ?)";
+    let rows: Vec<(String, String, String, i64, Money)> = sqlx::query_as(sql)
+        .bind(at)
+        .bind(source)
+        .bind(source)
+        .fetch_all(&mut *conn)
+        .await?;
+    if rows.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let rates = Rates::load(&mut *conn).await?;
+    let mut sums: HashMap<String, Decimal> = HashMap::new();
+    for (name, to, from, timestamp, Money(amount)) in rows {
+        *sums.entry(name).or_default() += rates.convert(amount, &from, &to, timestamp)?;
+    }
+    Ok(sums)
+}
+
+/// 追加 `rate_time` 列：与 `base` 同币种的记录不用换算，记为 0 以便合并，
+/// 其余为记录时间，按各自时间的汇率换算
+pub(super) fn push_rate_time(qb: &mut QueryBuilder<'_, Db>, base: &str) {
+    qb.push("CASE WHEN currency = ")
+        .push_bind(base.to_string())
+        .push(" THEN 0 ELSE timestamp END AS rate_time");
+}
+
+/// 每个币种只有一条一直生效的汇率
+impl<const N: usize> From<[(&str, Decimal); N]> for Rates {
+    fn from(rates: [(&str, Decimal); N]) -> Self {
+        Rates(
+            rates
+                .into_iter()
+                .map(|(currency, rate)| (currency.to_string(), vec![(i64::MIN, rate)]))
+                .collect(),
+        )
+    }
+}
+
+pub async fn get_exchange_rates(pool: &Pool<Db>) -> anyhow::Result<Vec<ExchangeRate>> {
+    let sql = "SELECT * FROM pixiu_exchange_rate ORDER BY currency, timestamp DESC";
+    let rows = sqlx::query_as(sql).fetch_all(pool).await?;
+    Ok(rows)
+}
+
+fn validate(currency: &str, rate: Decimal) -> anyhow::Result<String> {
+    if rate <= Decimal::ZERO {
+        return Err(StatusError::bad_request(format!("invalid exchange rate: {rate}")).into());
+    }
+    normalize(currency)
+}
+
+/// 新增汇率，同一币种同一时间已有汇率时覆盖，返回汇率的 id
+pub async fn insert_exchange_rate(pool: &Pool<Db>, info: ExchangeRate) -> anyhow::Result<u64> {
+    let currency = validate(&info.currency, info.rate)?;
+    let sql = format!(
+        "INSERT INTO pixiu_exchange_rate (currency, rate, timestamp) VALUES (?, ?, ?){}",
+        DIALECT.upsert("currency, timestamp", &["rate"])
+    );
+    let mut tx = pool.begin().await?;
+    sqlx::query(&sql)
+        .bind(&currency)
+        .bind(Money(info.rate))
+        .bind(info.timestamp)
+        .execute(&mut *tx)
+        .await?;
+    // 覆盖已有汇率时没有新插入的 id
+    let sql = "SELECT id FROM pixiu_exchange_rate WHERE currency = ? AND timestamp = ?";
+    let id: u32 = sqlx::query_scalar(sql)
+        .bind(&currency)
+        .bind(info.timestamp)
+        .fetch_one(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(id.into())
+}
+
+pub async fn delete_exchange_rate(pool: &Pool<Db>, id: u32) -> anyhow::Result<()> {
+    let sql = "DELETE FROM pixiu_exchange_rate WHERE id = ?";
+    sqlx::query(sql).bind(id).execute(pool).await?;
+    Ok(())
+}
+
+/// 解析汇率文件，每行为 `币种,汇率,日期`，日期为 `YYYY-MM-DD` 或时间戳，可带表头
+fn parse(bytes: &[u8]) -> anyhow::Result<Vec<(String, Decimal, i64)>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes));
+    let mut rates = vec![];
+    for (line, record) in reader.records().enumerate() {
+        let record = record?;
+        let invalid =
+            |message: &str| StatusError::bad_request(format!("line {}: {message}", line + 1));
+        let (Some(currency), Some(rate), Some(date)) =
+            (record.get(0), record.get(1), record.get(2))
+        else {
+            return Err(invalid("missing column").into());
+        };
+        let Ok(rate) = rate.parse::<Decimal>() else {
+            // 第一行可以是表头
+            if line == 0 {
+                continue;
+            }
+            return Err(invalid("invalid rate").into());
+        };
+        let timestamp = match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(date) => Shanghai
+                .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+                .unwrap()
+                .timestamp(),
+            Err(_) => date.parse().map_err(|_| invalid("invalid date"))?,
+        };
+        rates.push((validate(currency, rate)?, rate, timestamp));
+    }
+    Ok(rates)
+}
+
+/// 从文件批量导入汇率，返回写入条数
+pub async fn import_exchange_rates(pool: &Pool<Db>, bytes: &[u8]) -> anyhow::Result<usize> {
+    let rates = parse(bytes)?;
+    let mut tx = pool.begin().await?;
+    for chunk in rates.chunks(INSERT_BATCH_SIZE) {
+        let mut qb =
+            QueryBuilder::new("INSERT INTO pixiu_exchange_rate (currency, rate, timestamp) ");
+        qb.push_values(chunk, |mut b, (currency, rate, timestamp)| {
+            b.push_bind(currency.clone())
+                .push_bind(Money(*rate))
+                .push_bind(*timestamp);
+        });
+        qb.push(DIALECT.upsert("currency, timestamp", &["rate"]));
+        qb.build().execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(rates.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let rates = Rates::from([("USD", dec!(7.2)), ("HKD", dec!(0.9))]);
+        assert_eq!(rates.convert(dec!(10), "USD", "CNY", 0).unwrap(), dec!(72));
+        assert_eq!(rates.convert(dec!(72), "CNY", "USD", 0).unwrap(), dec!(10));
+        assert_eq!(rates.convert(dec!(10), "USD", "HKD", 0).unwrap(), dec!(80));
+        assert_eq!(rates.convert(dec!(1), "CNY", "USD", 0).unwrap(), dec!(0.14));
+        // 同币种无需汇率
+        assert_eq!(rates.convert(dec!(5), "JPY", "JPY", 0).unwrap(), dec!(5));
+        assert!(rates.convert(dec!(5), "JPY", "CNY", 0).is_err());
+    }
+
+    #[test]
+    fn test_convert_at_rate_in_effect() {
+        let rates = Rates(HashMap::from([(
+            "USD".to_string(),
+            vec![(100, dec!(7.0)), (200, dec!(7.2))],
+        )]));
+        assert_eq!(
+            rates.convert(dec!(10), "USD", "CNY", 150).unwrap(),
+            dec!(70)
+        );
+        assert_eq!(
+            rates.convert(dec!(10), "USD", "CNY", 200).unwrap(),
+            dec!(72)
+        );
+        assert_eq!(
+            rates.convert(dec!(10), "USD", "CNY", i64::MAX).unwrap(),
+            dec!(72)
+        );
+        // 早于第一条汇率时取第一条
+        assert_eq!(rates.convert(dec!(10), "USD", "CNY", 0).unwrap(), dec!(70));
+    }
+
+    #[test]
+    fn test_base_currency() {
+        assert_eq!(base(None).unwrap(), "CNY");
+        assert_eq!(base(Some("")).unwrap(), "CNY");
+        assert_eq!(base(Some(" usd")).unwrap(), "USD");
+        assert!(base(Some("US")).is_err());
+    }
+
+    #[test]
+    fn test_parse_rate_file() {
+        let file = "currency,rate,date\nusd,7.1,2024-01-01\nHKD, 0.91 ,1704038400\n";
+        let rates = parse(file.as_bytes()).unwrap();
+        assert_eq!(
+            rates,
+            vec![
+                ("USD".to_string(), dec!(7.1), 1704038400),
+                ("HKD".to_string(), dec!(0.91), 1704038400),
+            ]
+        );
+        assert!(parse(b"USD,7.1,2024-01-01\nHKD,abc,2024-01-01\n").is_err());
+        assert!(parse(b"USD,-1,2024-01-01\n").is_err());
+    }
+}
@@ -0,0 +1,398 @@
+use axum::body::Body;
+use futures_util::StreamExt;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use rust_xlsxwriter::Workbook;
+use sqlx::{Pool, QueryBuilder};
+use tokio::sync::mpsc;
+
+use super::{
+    currency::BASE_CURRENCY,
+    split::{FundSplit, FUND_LINES_SQL},
+    Db, FundFilter, FundInfo,
+};
+use crate::{storage::NullableMoney, utils};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const CSV_HEADER: [&str; 8] = [
+    "id",
+    "time",
+    "amount",
+    "name",
+    "class",
+    "source",
+    "timestamp",
+    "currency",
+];
+
+/// 导出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+    Beancount,
+}
+
+/// 导出的文件
+pub struct Export {
+    pub content_type: &'static str,
+    pub file_name: &'static str,
+    pub body: Body,
+}
+
+/// 按筛选条件导出资金记录，CSV 与 Beancount 边查边写，拆分的记录每个拆分一行或一个分录
+pub async fn export(
+    pool: Pool<Db>,
+    filter: FundFilter,
+    format: ExportFormat,
+) -> anyhow::Result<Export> {
+    let export = match format {
+        ExportFormat::Csv => Export {
+            content_type: "text/csv; charset=utf-8",
+            file_name: "pixiu.csv",
+            // 带 BOM 以便 Excel 正确识别中文
+            body: stream(
+                pool,
+                filter,
+                format!("\u{feff}{}", csv_line(&CSV_HEADER)?),
+                |fund| {
+                    csv_records(fund)
+                        .iter()
+                        .map(|record| csv_line(record))
+                        .collect()
+                },
+            ),
+        },
+        ExportFormat::Beancount => {
+            let header = beancount_header(&pool, &filter).await?;
+            Export {
+                content_type: "text/plain; charset=utf-8",
+                file_name: "pixiu.beancount",
+                body: stream(pool, filter, header, |fund| Ok(beancount_transaction(fund))),
+            }
+        }
+        ExportFormat::Xlsx => Export {
+            content_type: "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            file_name: "pixiu.xlsx",
+            body: Body::from(xlsx(&pool, &filter).await?),
+        },
+    };
+    Ok(export)
+}
+
+/// 资金记录连同拆分一起查询，拆分的记录每个拆分一行
+#[derive(sqlx::FromRow)]
+struct FundRow {
+    #[sqlx(flatten)]
+    fund: FundInfo,
+    split_class: Option<String>,
+    #[sqlx(try_from = "NullableMoney")]
+    split_amount: Option<Decimal>,
+}
+
+fn query(filter: &FundFilter) -> QueryBuilder<'static, Db> {
+    let mut qb = QueryBuilder::new(
+        "SELECT pfi.*, pfs.class AS split_class, pfs.amount AS split_amount
+        FROM (SELECT * FROM pixiu_fund_info",
+    );
+    filter.push_where(&mut qb);
+    qb.push(
+        ") pfi LEFT JOIN pixiu_fund_split pfs ON pfs.fund_id = pfi.id
+        ORDER BY pfi.timestamp, pfi.id, pfs.id",
+    );
+    qb
+}
+
+/// 将同一资金记录的连续行合并为带拆分的记录
+#[derive(Default)]
+struct Merge(Option<FundInfo>);
+
+impl Merge {
+    /// 加入一行，开始新的记录时返回已读完的上一条记录
+    fn push(&mut self, row: FundRow) -> Option<FundInfo> {
+        let split = match (row.split_class, row.split_amount) {
+            (Some(class), Some(amount)) => Some(FundSplit { class, amount }),
+            _ => None,
+        };
+        let done = match &mut self.0 {
+            Some(current) if current.id == row.fund.id => None,
+            current => current.replace(row.fund),
+        };
+        if let (Some(current), Some(split)) = (&mut self.0, split) {
+            current.splits.push(split);
+        }
+        done
+    }
+
+    fn finish(self) -> Option<FundInfo> {
+        self.0
+    }
+}
+
+/// 后台逐行查询并渲染，写入响应流
+fn stream<F>(pool: Pool<Db>, filter: FundFilter, header: String, render: F) -> Body
+where
+    F: Fn(&FundInfo) -> anyhow::Result<String> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel::<anyhow::Result<String>>(16);
+    tokio::spawn(async move {
+        if tx.send(Ok(header)).await.is_err() {
+            return;
+        }
+        let mut qb = query(&filter);
+        let mut rows = qb.build_query_as::<FundRow>().fetch(&pool);
+        let mut merge = Merge::default();
+        while let Some(row) = rows.next().await {
+            let chunk = match row {
+                Ok(row) => match merge.push(row) {
+                    Some(fund) => render(&fund),
+                    None => continue,
+                },
+                Err(err) => Err(err.into()),
+            };
+            // 客户端断开后停止查询
+            if tx.send(chunk).await.is_err() {
+                return;
+            }
+        }
+        if let Some(fund) = merge.finish() {
+            let _ = tx.send(render(&fund)).await;
+        }
+    });
+    Body::from_stream(futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }))
+}
+
+/// 每个拆分一行，未拆分的记录一行
+fn csv_records(fund: &FundInfo) -> Vec<[String; 8]> {
+    fund.lines()
+        .into_iter()
+        .map(|(class, amount)| {
+            [
+                fund.id.map(|id| id.to_string()).unwrap_or_default(),
+                utils::timestamp2time(fund.timestamp, TIME_FORMAT),
+                format!("{amount:.2}"),
+                fund.name.clone(),
+                class.to_string(),
+                fund.source.clone(),
+                fund.timestamp.to_string(),
+                fund.currency.clone(),
+            ]
+        })
+        .collect()
+}
+
+fn csv_line<T: AsRef<[u8]>>(record: &[T]) -> anyhow::Result<String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(record)?;
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+async fn xlsx(pool: &Pool<Db>, filter: &FundFilter) -> anyhow::Result<Vec<u8>> {
+    let mut qb = query(filter);
+    let rows: Vec<FundRow> = qb.build_query_as().fetch_all(pool).await?;
+    let mut merge = Merge::default();
+    let mut funds: Vec<FundInfo> = rows.into_iter().filter_map(|row| merge.push(row)).collect();
+    funds.extend(merge.finish());
+    let mut workbook = Workbook::new();
+    let worksheet = workbook.add_worksheet();
+    for (col, title) in CSV_HEADER.iter().enumerate() {
+        worksheet.write_string(0, col as u16, *title)?;
+    }
+    let lines = funds
+        .iter()
+        .flat_map(|fund| fund.lines().into_iter().map(move |line| (fund, line)));
+    for (i, (fund, (class, amount))) in lines.enumerate() {
+        let row = i as u32 + 1;
+        if let Some(id) = fund.id {
+            worksheet.write_number(row, 0, id)?;
+        }
+        worksheet.write_string(row, 1, utils::timestamp2time(fund.timestamp, TIME_FORMAT))?;
+        worksheet.write_number(row, 2, amount.to_f64().unwrap_or_default())?;
+        worksheet.write_string(row, 3, &fund.name)?;
+        worksheet.write_string(row, 4, class)?;
+        worksheet.write_string(row, 5, &fund.source)?;
+        worksheet.write_number(row, 6, fund.timestamp as f64)?;
+        worksheet.write_string(row, 7, &fund.currency)?;
+    }
+    Ok(workbook.save_to_buffer()?)
+}
+
+/// 资金来源作为资产账户，分类按收支方向作为收入或支出账户
+fn account(kind: &str, name: &str) -> String {
+    let mut component: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    match component.chars().next() {
+        Some(c) if c.is_ascii_lowercase() => {
+            component.replace_range(..1, &c.to_ascii_uppercase().to_string())
+        }
+        // 账户名需以大写字母、数字或非 ASCII 字符开头
+        Some(c) if c.is_ascii_uppercase() || c.is_ascii_digit() || !c.is_ascii() => {}
+        _ => component.insert(0, 'X'),
+    }
+    format!("{kind}:{component}")
+}
+
+fn asset_account(source: &str) -> String {
+    account("Assets", source)
+}
+
+fn category_account(class: &str, income: bool) -> String {
+    match income {
+        true => account("Income", class),
+        false => account("Expenses", class),
+    }
+}
+
+/// 开户指令，日期取导出范围内最早的记录，拆分的记录按拆分的分类开户
+async fn beancount_header(pool: &Pool<Db>, filter: &FundFilter) -> anyhow::Result<String> {
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT source, class, amount > 0, MIN(timestamp) FROM {FUND_LINES_SQL}
+        WHERE id IN (SELECT id FROM pixiu_fund_info"
+    ));
+    filter.push_where(&mut qb);
+    qb.push(") GROUP BY source, class, amount > 0");
+    let rows: Vec<(String, String, bool, i64)> = qb.build_query_as().fetch_all(pool).await?;
+
+    let mut opens = std::collections::BTreeMap::new();
+    for (source, class, income, timestamp) in rows {
+        for account in [asset_account(&source), category_account(&class, income)] {
+            let opened = opens.entry(account).or_insert(timestamp);
+            *opened = (*opened).min(timestamp);
+        }
+    }
+    let mut header = format!("option \"operating_currency\" \"{BASE_CURRENCY}\"\n\n");
+    for (account, timestamp) in opens {
+        let date = utils::timestamp2time(timestamp, DATE_FORMAT);
+        header.push_str(&format!("{date} open {account}\n"));
+    }
+    Ok(header)
+}
+
+/// 资产账户一个分录，每个拆分一个收支分录
+fn beancount_transaction(fund: &FundInfo) -> String {
+    let date = utils::timestamp2time(fund.timestamp, DATE_FORMAT);
+    let narration = fund.name.replace('\\', "\\\\").replace('"', "\\\"");
+    let currency = &fund.currency;
+    let mut text = format!(
+        "\n{date} * \"{narration}\"\n  {}  {:.2} {currency}\n",
+        asset_account(&fund.source),
+        fund.amount,
+    );
+    for (class, amount) in fund.lines() {
+        let account = category_account(class, amount > Decimal::ZERO);
+        text.push_str(&format!("  {account}  {:.2} {currency}\n", -amount));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn fund(amount: Decimal, name: &str, class: &str, source: &str) -> FundInfo {
+        FundInfo {
+            id: Some(7),
+            class: class.to_string(),
+            // 2024-01-05 12:34:56 +08:00
+            timestamp: 1704429296,
+            source: source.to_string(),
+            ..FundInfo::sample(amount, name)
+        }
+    }
+
+    #[test]
+    fn test_csv_quotes_fields() {
+        let records = csv_records(&fund(dec!(-28), "O'Brien, \"咖啡\"", "餐饮", "支付宝"));
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            csv_line(&records[0]).unwrap(),
+            "7,2024-01-05 12:34:56,-28.00,\"O'Brien, \"\"咖啡\"\"\",餐饮,支付宝,1704429296,CNY\n"
+        );
+    }
+
+    #[test]
+    fn test_account_names() {
+        assert_eq!(asset_account("支付宝"), "Assets:支付宝");
+        assert_eq!(asset_account("cmb card"), "Assets:Cmb-card");
+        assert_eq!(category_account("_other", false), "Expenses:X-other");
+        assert_eq!(category_account("工资:奖金", true), "Income:工资-奖金");
+    }
+
+    #[test]
+    fn test_beancount_transaction() {
+        let text = beancount_transaction(&fund(dec!(-28), "拿铁 \"大杯\"", "餐饮", "支付宝"));
+        assert_eq!(
+            text,
+            "\n2024-01-05 * \"拿铁 \\\"大杯\\\"\"\n  Assets:支付宝  -28.00 CNY\n  Expenses:餐饮  28.00 CNY\n"
+        );
+        let text = beancount_transaction(&fund(dec!(100), "工资", "工资", "招商银行"));
+        assert!(text.contains("  Income:工资  -100.00 CNY\n"));
+    }
+
+    fn split(class: &str, amount: Decimal) -> FundSplit {
+        FundSplit {
+            class: class.to_string(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn test_splits_become_legs() {
+        let fund = FundInfo {
+            splits: vec![split("食品", dec!(-70)), split("日用", dec!(-30))],
+            ..fund(dec!(-100), "超市", "购物", "支付宝")
+        };
+        let records = csv_records(&fund);
+        let lines: Vec<_> = records
+            .iter()
+            .map(|record| (record[2].as_str(), record[4].as_str()))
+            .collect();
+        assert_eq!(lines, vec![("-70.00", "食品"), ("-30.00", "日用")]);
+        assert_eq!(
+            beancount_transaction(&fund),
+            "\n2024-01-05 * \"超市\"\n  Assets:支付宝  -100.00 CNY\n  \
+            Expenses:食品  70.00 CNY\n  Expenses:日用  30.00 CNY\n"
+        );
+    }
+
+    fn row(id: u32, split: Option<(&str, Decimal)>) -> FundRow {
+        FundRow {
+            fund: FundInfo {
+                id: Some(id),
+                ..FundInfo::sample(dec!(-100), "超市")
+            },
+            split_class: split.map(|(class, _)| class.to_string()),
+            split_amount: split.map(|(_, amount)| amount),
+        }
+    }
+
+    #[test]
+    fn test_merge_rows_of_a_fund() {
+        let mut merge = Merge::default();
+        assert!(merge.push(row(1, None)).is_none());
+        let first = merge.push(row(2, Some(("食品", dec!(-70))))).unwrap();
+        assert_eq!(first.id, Some(1));
+        assert!(first.splits.is_empty());
+        assert!(merge.push(row(2, Some(("日用", dec!(-30))))).is_none());
+        let second = merge.finish().unwrap();
+        assert_eq!(second.id, Some(2));
+        assert_eq!(
+            second.splits,
+            vec![split("食品", dec!(-70)), split("日用", dec!(-30))]
+        );
+    }
+}
@@ -0,0 +1,330 @@
+use chrono::{NaiveDateTime, TimeZone};
+use chrono_tz::Asia::Shanghai;
+use rust_decimal::Decimal;
+use sqlx::Pool;
+
+use super::{budget, currency, insert_fund, rule, Db, FundInfo};
+use crate::api::error::StatusError;
+
+/// 判定为同一笔交易的最大时间差（秒）
+const SAME_TIME_WINDOW: i64 = 5 * 60;
+/// 交易对方一致时允许的最大时间差（秒），手工记账时间往往不精确
+const SAME_COUNTERPARTY_WINDOW: i64 = 24 * 60 * 60;
+
+/// 账单来源平台
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Platform {
+    Alipay,
+    Wechat,
+}
+
+impl Platform {
+    /// 导入记录默认的资金来源
+    fn source(&self) -> &'static str {
+        match self {
+            Platform::Alipay => "支付宝",
+            Platform::Wechat => "微信",
+        }
+    }
+}
+
+/// 待导入的一条记录
+#[derive(Debug, serde::Serialize)]
+pub struct ImportRow {
+    #[serde(flatten)]
+    fund: FundInfo,
+    counterparty: String,
+    /// 疑似重复的已有记录
+    duplicate_of: Option<u32>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ImportPreview {
+    rows: Vec<ImportRow>,
+    /// 不计收支、交易关闭等被跳过的行数
+    skipped: usize,
+}
+
+/// 账单中一列可能的表头，兼容新旧版导出格式
+struct Columns {
+    time: usize,
+    class: Option<usize>,
+    counterparty: usize,
+    goods: Option<usize>,
+    direction: usize,
+    amount: usize,
+    status: Option<usize>,
+}
+
+impl Columns {
+    fn find(header: &[String]) -> Option<Self> {
+        let position = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+        Some(Columns {
+            time: position(&["交易时间", "交易创建时间"])?,
+            class: position(&["交易分类", "交易类型", "类型"]),
+            counterparty: position(&["交易对方"])?,
+            goods: position(&["商品说明", "商品名称", "商品"]),
+            direction: position(&["收/支"])?,
+            amount: position(&["金额", "金额（元）", "金额(元)"])?,
+            status: position(&["交易状态", "当前状态"]),
+        })
+    }
+}
+
+/// 解码账单文件，优先按 UTF-8（去掉 BOM），否则按 GBK
+fn decode(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::GBK.decode(bytes).0.into_owned(),
+    }
+}
+
+/// 解析支付宝、微信支付导出的账单 CSV
+pub fn parse(
+    platform: Platform,
+    source: Option<String>,
+    bytes: &[u8],
+) -> anyhow::Result<(Vec<(FundInfo, String)>, usize)> {
+    let text = decode(bytes);
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let source = source
+        .filter(|source| !source.is_empty())
+        .unwrap_or_else(|| platform.source().to_string());
+
+    let mut columns = None;
+    let (mut funds, mut skipped) = (vec![], 0);
+    for record in reader.records() {
+        let record =
+            record.map_err(|err| StatusError::bad_request(format!("invalid bill file: {err}")))?;
+        let record: Vec<String> = record.iter().map(|cell| cell.trim().to_string()).collect();
+        // 表头之前是账单说明
+        let Some(columns) = &columns else {
+            columns = Columns::find(&record);
+            continue;
+        };
+        let cell = |index: usize| record.get(index).map(String::as_str).unwrap_or_default();
+        let Ok(time) = NaiveDateTime::parse_from_str(cell(columns.time), "%Y-%m-%d %H:%M:%S")
+        else {
+            // 表尾的统计信息
+            continue;
+        };
+        let status = columns.status.map(cell).unwrap_or_default();
+        let sign = match cell(columns.direction) {
+            "支出" => Decimal::NEGATIVE_ONE,
+            "收入" => Decimal::ONE,
+            _ => Decimal::ZERO,
+        };
+        if sign.is_zero() || status.contains("关闭") || status.contains("失败") {
+            skipped += 1;
+            continue;
+        }
+        let amount: Decimal = cell(columns.amount)
+            .trim_start_matches(['¥', '￥'])
+            .replace(',', "")
+            .parse()
+            .map_err(|_| {
+                let message = format!("invalid amount {:?} at {time}", cell(columns.amount));
+                StatusError::bad_request(message)
+            })?;
+        let counterparty = cell(columns.counterparty).to_string();
+        let goods = columns
+            .goods
+            .map(cell)
+            .filter(|goods| !goods.is_empty() && *goods != "/");
+        let name = match goods {
+            Some(goods) => format!("{counterparty} {goods}"),
+            None => counterparty.clone(),
+        };
+        let timestamp = Shanghai
+            .from_local_datetime(&time)
+            .earliest()
+            .map(|time| time.timestamp())
+            .unwrap_or_default();
+        funds.push((
+            FundInfo {
+                id: None,
+                amount: sign * amount,
+                name,
+                class: columns.class.map(cell).unwrap_or_default().to_string(),
+                timestamp,
+                source: source.clone(),
+                // 支付宝、微信账单均以人民币计
+                currency: currency::default_currency(),
+                tags: vec![],
+                splits: vec![],
+                reconciled: false,
+            },
+            counterparty,
+        ));
+    }
+    if columns.is_none() {
+        return Err(StatusError::bad_request("unrecognized bill file").into());
+    }
+    Ok((funds, skipped))
+}
+
+/// 在已有记录中查找同一笔交易：金额、来源相同，且时间几乎一致或交易对方一致
+fn find_duplicate(fund: &FundInfo, counterparty: &str, existing: &[FundInfo]) -> Option<u32> {
+    existing
+        .iter()
+        .find(|old| {
+            let elapsed = (old.timestamp - fund.timestamp).abs();
+            old.source == fund.source
+                && old.amount == fund.amount
+                && (elapsed <= SAME_TIME_WINDOW
+                    || (elapsed <= SAME_COUNTERPARTY_WINDOW
+                        && !counterparty.is_empty()
+                        && old.name.contains(counterparty)))
+        })
+        .and_then(|old| old.id)
+}
+
+/// 解析账单，按自动分类规则修改后标记疑似重复的记录，不写入数据库
+pub async fn preview(
+    pool: &Pool<Db>,
+    platform: Platform,
+    source: Option<String>,
+    bytes: &[u8],
+) -> anyhow::Result<ImportPreview> {
+    let (mut funds, skipped) = parse(platform, source, bytes)?;
+    let rules = rule::Rules::load(pool).await?;
+    for (fund, _) in funds.iter_mut() {
+        rules.apply(fund);
+    }
+    let from = funds.iter().map(|(fund, _)| fund.timestamp).min();
+    let to = funds.iter().map(|(fund, _)| fund.timestamp).max();
+    let existing: Vec<FundInfo> = match from.zip(to) {
+        Some((from, to)) => {
+            let sql = "SELECT * FROM pixiu_fund_info
+                WHERE timestamp BETWEEN ? AND ? AND deleted_at IS NULL";
+            sqlx::query_as(sql)
+                .bind(from - SAME_COUNTERPARTY_WINDOW)
+                .bind(to + SAME_COUNTERPARTY_WINDOW)
+                .fetch_all(pool)
+                .await?
+        }
+        None => vec![],
+    };
+    let rows = funds
+        .into_iter()
+        .map(|(fund, counterparty)| ImportRow {
+            duplicate_of: find_duplicate(&fund, &counterparty, &existing),
+            fund,
+            counterparty,
+        })
+        .collect();
+    Ok(ImportPreview { rows, skipped })
+}
+
+/// 在一个事务中写入确认后的记录，返回写入条数
+///
+/// 与单条新增相同：未指定币种时取资产账户的币种，写入拆分、标签和审计日志，
+/// 任一条落在已对账的时间段内时整体回滚，写入后发送预算提醒
+pub async fn commit(pool: &Pool<Db>, funds: Vec<FundInfo>, client: &str) -> anyhow::Result<u64> {
+    let mut tx = pool.begin().await?;
+    for fund in &funds {
+        insert_fund(&mut tx, fund, client).await?;
+    }
+    tx.commit().await?;
+    let count = funds.len() as u64;
+    budget::spawn_notify(pool, funds);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    const ALIPAY: &str = "支付宝交易明细
+导出信息：
+姓名：张三
+------------------------支付宝支付科技有限公司  电子客户回单------------------------
+交易时间,交易分类,交易对方,对方账号,商品说明,收/支,金额,收/付款方式,交易状态,交易订单号,商家订单号,备注,
+2024-01-05 12:34:56,餐饮美食,\"O'Brien, 咖啡\",obr***@x.com,拿铁,支出,28.00,招商银行储蓄卡(1234),交易成功,2024010522001,T123,,
+2024-01-06 09:00:00,转账红包,李四,li***@x.com,转账,收入,100.50,,交易成功,2024010622002,,,
+2024-01-07 10:00:00,投资理财,余额宝,,余额宝-自动转入,不计收支,10.00,,交易成功,2024010722003,,,
+2024-01-08 10:00:00,日用百货,超市,,购物,支出,5.00,,交易关闭,2024010822004,,,
+";
+
+    const WECHAT: &str = "\u{feff}微信支付账单明细,,,,,,,,,,
+微信昵称：[张三],,,,,,,,,,
+----------------------微信支付账单明细列表--------------------,,,,,,,,,,
+交易时间,交易类型,交易对方,商品,收/支,金额(元),支付方式,当前状态,交易单号,商户单号,备注
+2024-02-01 08:00:00,商户消费,早餐店,/,支出,\"¥1,234.50\",零钱,支付成功,4200001,1001,/
+2024-02-02 18:00:00,微信红包,王五,/,收入,¥8.88,/,已存入零钱,1000002,/,/
+2024-02-03 18:00:00,零钱提现,招商银行(1234),/,/,¥50.00,零钱,提现已到账,1000003,/,/
+";
+
+    #[test]
+    fn test_parse_alipay_gbk() {
+        let (bytes, _, _) = encoding_rs::GBK.encode(ALIPAY);
+        let (funds, skipped) = parse(Platform::Alipay, None, &bytes).unwrap();
+        assert_eq!(skipped, 2);
+        assert_eq!(funds.len(), 2);
+        let (fund, counterparty) = &funds[0];
+        assert_eq!(counterparty, "O'Brien, 咖啡");
+        assert_eq!(fund.name, "O'Brien, 咖啡 拿铁");
+        assert_eq!(fund.amount, dec!(-28.00));
+        assert_eq!(fund.class, "餐饮美食");
+        assert_eq!(fund.source, "支付宝");
+        // 2024-01-05 12:34:56 +08:00
+        assert_eq!(fund.timestamp, 1704429296);
+        assert_eq!(funds[1].0.amount, dec!(100.50));
+    }
+
+    #[test]
+    fn test_parse_wechat_utf8() {
+        let (funds, skipped) = parse(
+            Platform::Wechat,
+            Some("零钱".to_string()),
+            WECHAT.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(skipped, 1);
+        assert_eq!(funds.len(), 2);
+        assert_eq!(funds[0].0.name, "早餐店");
+        assert_eq!(funds[0].0.amount, dec!(-1234.50));
+        assert_eq!(funds[0].0.source, "零钱");
+        assert_eq!(funds[1].0.amount, dec!(8.88));
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_file() {
+        assert!(parse(Platform::Alipay, None, b"a,b,c\n1,2,3\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_amount() {
+        let bill = ALIPAY.replace("支出,28.00,", "支出,二十八,");
+        let err = parse(Platform::Alipay, None, bill.as_bytes()).unwrap_err();
+        let StatusError(status, _) = err.downcast_ref::<StatusError>().unwrap();
+        assert_eq!(*status, axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_find_duplicate() {
+        let fund = |id, amount, name: &str, timestamp| FundInfo {
+            id: Some(id),
+            timestamp,
+            ..FundInfo::sample(amount, name)
+        };
+        let imported = fund(0, dec!(-28), "星巴克 拿铁", 1_000_000);
+        let existing = vec![
+            fund(1, dec!(-28), "咖啡", 1_000_000 - 2 * SAME_TIME_WINDOW),
+            fund(2, dec!(-28), "星巴克", 1_000_000 + 3600),
+        ];
+        assert_eq!(find_duplicate(&imported, "星巴克", &existing), Some(2));
+        assert_eq!(find_duplicate(&imported, "瑞幸", &existing), None);
+        let existing = vec![fund(3, dec!(-28), "咖啡", 1_000_000 + 60)];
+        assert_eq!(find_duplicate(&imported, "瑞幸", &existing), Some(3));
+        let existing = vec![fund(4, dec!(-27), "星巴克", 1_000_000)];
+        assert_eq!(find_duplicate(&imported, "星巴克", &existing), None);
+    }
+}
@@ -0,0 +1,542 @@
+use chrono::{Months, TimeZone};
+use chrono_tz::Asia::Shanghai;
+use rust_decimal::{prelude::ToPrimitive, Decimal, RoundingStrategy};
+use rust_decimal_macros::dec;
+use sqlx::{encode::IsNull, error::BoxDynError, Database, Decode, Encode, Type};
+
+use crate::api::error::StatusError;
+
+/// 贷款期限上限，50 年
+pub const MAX_TERM_MONTHS: u32 = 600;
+/// 本金上限，与金额列 DECIMAL(15, 2) 一致
+const MAX_PRINCIPAL: Decimal = dec!(9_999_999_999_999.99);
+
+/// 还款方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepaymentMethod {
+    /// 等额本息：每期还款额相同
+    EqualInstallment,
+    /// 等额本金：每期归还本金相同
+    EqualPrincipal,
+}
+
+impl RepaymentMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RepaymentMethod::EqualInstallment => "equal_installment",
+            RepaymentMethod::EqualPrincipal => "equal_principal",
+        }
+    }
+}
+
+impl std::str::FromStr for RepaymentMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "equal_installment" => Ok(RepaymentMethod::EqualInstallment),
+            "equal_principal" => Ok(RepaymentMethod::EqualPrincipal),
+            _ => anyhow::bail!("unknown repayment method: {s}"),
+        }
+    }
+}
+
+// 以字符串形式存入 VARCHAR 列
+impl<DB: Database> Type<DB> for RepaymentMethod
+where
+    str: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <str as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <str as Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, DB: Database> Encode<'q, DB> for RepaymentMethod
+where
+    &'q str: Encode<'q, DB>,
+{
+    fn encode_by_ref(&self, buf: &mut DB::ArgumentBuffer<'q>) -> Result<IsNull, BoxDynError> {
+        <&str as Encode<DB>>::encode(self.as_str(), buf)
+    }
+}
+
+impl<'r, DB: Database> Decode<'r, DB> for RepaymentMethod
+where
+    &'r str: Decode<'r, DB>,
+{
+    fn decode(value: DB::ValueRef<'r>) -> Result<Self, BoxDynError> {
+        let s = <&str as Decode<DB>>::decode(value)?;
+        Ok(s.parse()?)
+    }
+}
+
+/// 提前还款后保持不变的项
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrepayKeep {
+    /// 期限不变，减少月供
+    #[default]
+    Term,
+    /// 月供（等额本金时为每期本金）不变，缩短期限
+    Payment,
+}
+
+/// 贷款条款
+#[derive(Debug, Clone, Copy)]
+pub struct Loan {
+    pub principal: Decimal,
+    /// 年利率，百分比，如 4.9 表示 4.9%
+    pub annual_rate: Decimal,
+    pub term_months: u32,
+    pub method: RepaymentMethod,
+    /// 首次还款时间
+    pub start_timestamp: i64,
+}
+
+/// 还款计划中的一期
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Installment {
+    pub period: u32,
+    pub timestamp: i64,
+    pub payment: Decimal,
+    pub principal: Decimal,
+    pub interest: Decimal,
+    /// 本期还款后的剩余本金
+    pub remaining: Decimal,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Schedule {
+    pub installments: Vec<Installment>,
+    pub total_payment: Decimal,
+    pub total_interest: Decimal,
+}
+
+/// 提前还款对剩余还款计划的影响
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Prepayment {
+    pub amount: Decimal,
+    pub timestamp: i64,
+    pub keep: PrepayKeep,
+    /// 提前还款前的剩余本金
+    pub remaining_before: Decimal,
+    /// 不提前还款时剩余的还款计划
+    pub original: Schedule,
+    /// 提前还款后剩余的还款计划
+    pub adjusted: Schedule,
+    pub interest_saved: Decimal,
+    pub periods_saved: u32,
+}
+
+impl Loan {
+    fn monthly_rate(&self) -> Decimal {
+        self.annual_rate / dec!(100) / dec!(12)
+    }
+
+    /// 检查贷款条款，超出范围时返回 400
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.term_months == 0 || self.term_months > MAX_TERM_MONTHS {
+            let message = format!("term_months must be between 1 and {MAX_TERM_MONTHS}");
+            return Err(StatusError::bad_request(message).into());
+        }
+        if self.principal <= Decimal::ZERO || self.principal > MAX_PRINCIPAL {
+            let message = format!("principal must be positive and at most {MAX_PRINCIPAL}");
+            return Err(StatusError::bad_request(message).into());
+        }
+        if self.annual_rate < Decimal::ZERO || self.annual_rate > dec!(100) {
+            return Err(StatusError::bad_request("annual_rate must be between 0 and 100").into());
+        }
+        Ok(())
+    }
+
+    /// 完整的逐月还款计划
+    pub fn schedule(&self) -> anyhow::Result<Schedule> {
+        self.validate()?;
+        build_schedule(
+            self.principal,
+            self.monthly_rate(),
+            self.term_months,
+            self.method,
+            self.start_timestamp,
+            1,
+        )
+    }
+
+    /// 在 `timestamp` 提前归还 `amount`，
+    /// 视为在该时间及之前最后一期还款后立即归还，不计算不足一期的利息
+    pub fn prepay(
+        &self,
+        amount: Decimal,
+        timestamp: i64,
+        keep: PrepayKeep,
+    ) -> anyhow::Result<Prepayment> {
+        if amount <= Decimal::ZERO {
+            return Err(StatusError::bad_request("prepay_amount must be positive").into());
+        }
+        let full = self.schedule()?;
+        let paid = full
+            .installments
+            .iter()
+            .take_while(|installment| installment.timestamp <= timestamp)
+            .count();
+        let remaining_before = match paid {
+            0 => self.principal,
+            _ => full.installments[paid - 1].remaining,
+        };
+        let original_installments = full.installments[paid..].to_vec();
+        let original = summarize(original_installments);
+
+        let rate = self.monthly_rate();
+        let periods_left = self.term_months - paid as u32;
+        let principal = round2((remaining_before - amount).max(Decimal::ZERO));
+        let periods = match keep {
+            _ if principal.is_zero() => 0,
+            PrepayKeep::Term => periods_left,
+            PrepayKeep::Payment => shortened_periods(self, principal, rate, periods_left)?,
+        };
+        let first_timestamp = period_timestamp(self.start_timestamp, paid as u32)?;
+        let adjusted = build_schedule(
+            principal,
+            rate,
+            periods,
+            self.method,
+            first_timestamp,
+            paid as u32 + 1,
+        )?;
+        Ok(Prepayment {
+            amount: remaining_before - principal,
+            timestamp,
+            keep,
+            remaining_before,
+            interest_saved: original.total_interest - adjusted.total_interest,
+            periods_saved: original.installments.len() as u32 - adjusted.installments.len() as u32,
+            original,
+            adjusted,
+        })
+    }
+}
+
+/// 月供不变时还清 `principal` 所需的期数，不超过 `limit`
+fn shortened_periods(
+    loan: &Loan,
+    principal: Decimal,
+    rate: Decimal,
+    limit: u32,
+) -> anyhow::Result<u32> {
+    Ok(match loan.method {
+        // 按原月供逐期扣减，与还款计划的舍入方式一致
+        RepaymentMethod::EqualInstallment => {
+            let payment = installment_payment(loan.principal, rate, loan.term_months)?;
+            let mut remaining = principal;
+            let mut periods = 0;
+            while remaining > Decimal::ZERO && periods < limit {
+                remaining -= round2(payment - round2(remaining * rate));
+                periods += 1;
+            }
+            periods
+        }
+        RepaymentMethod::EqualPrincipal => (principal * Decimal::from(loan.term_months)
+            / loan.principal)
+            .ceil()
+            .to_u32()
+            .map_or(limit, |periods| periods.min(limit)),
+    })
+}
+
+/// 等额本息每期还款额，未舍入，超出 Decimal 范围时返回 400
+fn installment_payment(principal: Decimal, rate: Decimal, periods: u32) -> anyhow::Result<Decimal> {
+    if periods == 0 {
+        return Ok(Decimal::ZERO);
+    }
+    if rate.is_zero() {
+        return Ok(principal / Decimal::from(periods));
+    }
+    let factor = (0..periods).try_fold(Decimal::ONE, |factor, _| {
+        factor.checked_mul(Decimal::ONE + rate)
+    });
+    factor
+        .and_then(|factor| {
+            principal
+                .checked_mul(rate)?
+                .checked_mul(factor)?
+                .checked_div(factor - Decimal::ONE)
+        })
+        .ok_or_else(|| {
+            let message = "principal, annual_rate and term_months are too large to compute";
+            StatusError::bad_request(message).into()
+        })
+}
+
+fn build_schedule(
+    principal: Decimal,
+    rate: Decimal,
+    periods: u32,
+    method: RepaymentMethod,
+    first_timestamp: i64,
+    first_period: u32,
+) -> anyhow::Result<Schedule> {
+    // 等额本金不需要计算月供
+    let payment = match method {
+        RepaymentMethod::EqualInstallment => Some(installment_payment(principal, rate, periods)?),
+        RepaymentMethod::EqualPrincipal => None,
+    };
+    let mut remaining = principal;
+    let mut installments = Vec::with_capacity(periods as usize);
+    for i in 0..periods {
+        let interest = round2(remaining * rate);
+        let principal_part = if i + 1 == periods {
+            // 最后一期结清，吸收舍入误差
+            remaining
+        } else {
+            match payment {
+                Some(payment) => round2(payment - interest),
+                None => round2(principal / Decimal::from(periods)),
+            }
+        };
+        remaining -= principal_part;
+        installments.push(Installment {
+            period: first_period + i,
+            timestamp: period_timestamp(first_timestamp, i)?,
+            payment: principal_part + interest,
+            principal: principal_part,
+            interest,
+            remaining,
+        });
+    }
+    Ok(summarize(installments))
+}
+
+fn summarize(installments: Vec<Installment>) -> Schedule {
+    let total_payment = installments.iter().map(|i| i.payment).sum();
+    let total_interest = installments.iter().map(|i| i.interest).sum();
+    Schedule {
+        installments,
+        total_payment,
+        total_interest,
+    }
+}
+
+/// 首期之后第 `offset` 期的还款时间，按上海时区逐月递增
+fn period_timestamp(first_timestamp: i64, offset: u32) -> anyhow::Result<i64> {
+    Shanghai
+        .timestamp_opt(first_timestamp, 0)
+        .single()
+        .and_then(|first| first.checked_add_months(Months::new(offset)))
+        .map(|time| time.timestamp())
+        .ok_or_else(|| StatusError::bad_request("start_timestamp is out of range").into())
+}
+
+/// 每期的利息和本金舍入到分，四舍五入
+fn round2(value: Decimal) -> Decimal {
+    value.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-01-15 00:00:00 +08:00
+    const START: i64 = 1705248000;
+
+    fn mortgage(method: RepaymentMethod) -> Loan {
+        Loan {
+            principal: dec!(1_000_000),
+            annual_rate: dec!(4.9),
+            term_months: 360,
+            method,
+            start_timestamp: START,
+        }
+    }
+
+    #[test]
+    fn test_equal_installment_schedule() {
+        let schedule = mortgage(RepaymentMethod::EqualInstallment)
+            .schedule()
+            .unwrap();
+        assert_eq!(schedule.installments.len(), 360);
+        let first = &schedule.installments[0];
+        assert_eq!(first.payment, dec!(5307.27));
+        assert_eq!(first.interest, dec!(4083.33));
+        assert_eq!(first.principal, dec!(1223.94));
+        let last = schedule.installments.last().unwrap();
+        assert!(last.remaining.is_zero());
+        // 逐期舍入到分，与公式值 5307.2704 * 360 - 1000000 相差几元以内
+        assert!((schedule.total_interest - dec!(910_617.34)).abs() < dec!(5));
+    }
+
+    #[test]
+    fn test_equal_principal_schedule() {
+        let schedule = mortgage(RepaymentMethod::EqualPrincipal)
+            .schedule()
+            .unwrap();
+        let first = &schedule.installments[0];
+        assert_eq!(first.principal, dec!(2777.78));
+        assert_eq!(first.interest, dec!(4083.33));
+        assert_eq!(first.payment, dec!(6861.11));
+        assert!(schedule.installments.last().unwrap().remaining.is_zero());
+        assert!((schedule.total_interest - dec!(737_041.67)).abs() < dec!(1));
+    }
+
+    #[test]
+    fn test_period_timestamp_keeps_day_of_month() {
+        let schedule = mortgage(RepaymentMethod::EqualPrincipal)
+            .schedule()
+            .unwrap();
+        // 2024-02-15 00:00:00 +08:00
+        assert_eq!(schedule.installments[1].timestamp, 1707926400);
+    }
+
+    #[test]
+    fn test_zero_rate() {
+        let loan = Loan {
+            principal: dec!(1200),
+            annual_rate: Decimal::ZERO,
+            term_months: 12,
+            method: RepaymentMethod::EqualInstallment,
+            start_timestamp: START,
+        };
+        let schedule = loan.schedule().unwrap();
+        assert!(schedule.installments.iter().all(|i| i.payment == dec!(100)));
+        assert!(schedule.total_interest.is_zero());
+    }
+
+    #[test]
+    fn test_prepay_keep_term() {
+        let loan = mortgage(RepaymentMethod::EqualInstallment);
+        let at = loan.schedule().unwrap().installments[11].timestamp;
+        let prepay = loan.prepay(dec!(100_000), at, PrepayKeep::Term).unwrap();
+        assert_eq!(prepay.original.installments.len(), 348);
+        assert_eq!(prepay.adjusted.installments.len(), 348);
+        assert_eq!(prepay.adjusted.installments[0].period, 13);
+        assert_eq!(prepay.periods_saved, 0);
+        assert!(prepay.adjusted.installments[0].payment < dec!(5307.27));
+        assert!(prepay.interest_saved > Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_prepay_keep_payment() {
+        let loan = mortgage(RepaymentMethod::EqualInstallment);
+        let at = loan.schedule().unwrap().installments[11].timestamp;
+        let keep_term = loan.prepay(dec!(100_000), at, PrepayKeep::Term).unwrap();
+        let keep_payment = loan.prepay(dec!(100_000), at, PrepayKeep::Payment).unwrap();
+        assert!(keep_payment.periods_saved > 0);
+        assert!(keep_payment.adjusted.installments[0].payment <= dec!(5307.27));
+        assert!(keep_payment.interest_saved > keep_term.interest_saved);
+        let last = keep_payment.adjusted.installments.last().unwrap();
+        assert!(last.remaining.is_zero());
+    }
+
+    #[test]
+    fn test_prepay_in_full() {
+        let loan = mortgage(RepaymentMethod::EqualPrincipal);
+        let prepay = loan
+            .prepay(dec!(2_000_000), START - 1, PrepayKeep::Payment)
+            .unwrap();
+        assert_eq!(prepay.amount, dec!(1_000_000));
+        assert!(prepay.adjusted.installments.is_empty());
+        assert_eq!(prepay.periods_saved, 360);
+    }
+
+    #[test]
+    fn test_schedule_adds_up_to_the_cent() {
+        for method in [
+            RepaymentMethod::EqualInstallment,
+            RepaymentMethod::EqualPrincipal,
+        ] {
+            let loan = mortgage(method);
+            let schedule = loan.schedule().unwrap();
+            let principal: Decimal = schedule.installments.iter().map(|i| i.principal).sum();
+            assert_eq!(principal, loan.principal);
+            assert_eq!(
+                schedule.total_payment,
+                loan.principal + schedule.total_interest
+            );
+            assert!(schedule
+                .installments
+                .iter()
+                .all(|i| i.interest.scale() <= 2));
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_is_rejected() {
+        let loan = mortgage(RepaymentMethod::EqualInstallment);
+        for term_months in [0, MAX_TERM_MONTHS + 1, u32::MAX] {
+            assert!(Loan {
+                term_months,
+                ..loan
+            }
+            .schedule()
+            .is_err());
+        }
+        assert!(Loan {
+            annual_rate: dec!(-1),
+            ..loan
+        }
+        .schedule()
+        .is_err());
+        assert!(Loan {
+            start_timestamp: i64::MAX,
+            ..loan
+        }
+        .schedule()
+        .is_err());
+        assert!(Loan {
+            principal: MAX_PRINCIPAL + dec!(0.01),
+            ..loan
+        }
+        .schedule()
+        .is_err());
+        assert!(loan.prepay(Decimal::ZERO, START, PrepayKeep::Term).is_err());
+        assert!(loan.prepay(dec!(-100), START, PrepayKeep::Term).is_err());
+        let err = loan
+            .prepay(dec!(-0.01), START, PrepayKeep::Term)
+            .unwrap_err();
+        let StatusError(status, _) = err.downcast_ref::<StatusError>().unwrap();
+        assert_eq!(*status, axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_limits_do_not_overflow() {
+        let largest = Loan {
+            principal: dec!(10_000_000_000),
+            annual_rate: dec!(100),
+            term_months: MAX_TERM_MONTHS,
+            method: RepaymentMethod::EqualInstallment,
+            start_timestamp: START,
+        };
+        for loan in [
+            largest,
+            Loan {
+                principal: MAX_PRINCIPAL,
+                ..largest
+            },
+        ] {
+            let err = loan.schedule().unwrap_err();
+            let StatusError(status, _) = err.downcast_ref::<StatusError>().unwrap();
+            assert_eq!(*status, axum::http::StatusCode::BAD_REQUEST);
+            assert!(loan.prepay(dec!(1), START, PrepayKeep::Payment).is_err());
+        }
+        let schedule = Loan {
+            principal: MAX_PRINCIPAL,
+            annual_rate: dec!(10),
+            ..largest
+        }
+        .schedule()
+        .unwrap();
+        assert_eq!(schedule.installments.len(), MAX_TERM_MONTHS as usize);
+        assert!(schedule.installments.last().unwrap().remaining.is_zero());
+        let schedule = Loan {
+            method: RepaymentMethod::EqualPrincipal,
+            ..largest
+        }
+        .schedule()
+        .unwrap();
+        assert!(schedule.installments.last().unwrap().remaining.is_zero());
+    }
+}
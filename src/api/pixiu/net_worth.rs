@@ -0,0 +1,123 @@
+use chrono::TimeZone;
+use chrono_tz::Asia::Shanghai;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use sqlx::{Pool, QueryBuilder};
+
+use super::{currency, get_debt_info, get_property_info, Db};
+use crate::{storage::Money, utils};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// 某日的资产、负债与净资产
+#[derive(sqlx::FromRow, Debug, PartialEq, serde::Serialize)]
+pub struct NetWorth {
+    /// 快照日期（上海时区零点）
+    timestamp: i64,
+    #[sqlx(try_from = "Money")]
+    assets: Decimal,
+    #[sqlx(try_from = "Money")]
+    liabilities: Decimal,
+    #[sqlx(try_from = "Money")]
+    net_worth: Decimal,
+}
+
+/// `timestamp` 所在日（上海时区）零点
+fn day_start(timestamp: i64) -> i64 {
+    let date = Shanghai.timestamp_opt(timestamp, 0).unwrap().date_naive();
+    Shanghai
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+        .unwrap()
+        .timestamp()
+}
+
+/// 记录 `now` 当天各资产账户余额（折合人民币）和未还清欠款，同一天重复执行时覆盖
+pub async fn snapshot(pool: &Pool<Db>, now: i64) -> anyhow::Result<()> {
+    let timestamp = day_start(now);
+    let mut items: Vec<(bool, u32, String, Decimal)> = vec![];
+    for property in get_property_info(pool, false, currency::BASE_CURRENCY).await? {
+        if let Some(id) = property.id {
+            items.push((false, id, property.name, property.base_amount));
+        }
+    }
+    for debt in get_debt_info(pool).await? {
+        if let Some(id) = debt.id.filter(|_| debt.remaining > Decimal::ZERO) {
+            items.push((true, id, debt.name, debt.remaining));
+        }
+    }
+
+    let mut tx = pool.begin().await?;
+    let sql = "DELETE FROM pixiu_balance_history WHERE timestamp = ?";
+    sqlx::query(sql).bind(timestamp).execute(&mut *tx).await?;
+    if !items.is_empty() {
+        let mut qb = QueryBuilder::new(
+            "INSERT INTO pixiu_balance_history (timestamp, liability, item_id, name, amount) ",
+        );
+        qb.push_values(items, |mut b, (liability, id, name, amount)| {
+            b.push_bind(timestamp)
+                .push_bind(liability)
+                .push_bind(id)
+                .push_bind(name)
+                .push_bind(Money(amount));
+        });
+        qb.build().execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// 按日汇总的净资产变化
+pub async fn get_history(pool: &Pool<Db>, from: i64, to: i64) -> anyhow::Result<Vec<NetWorth>> {
+    let sql = "SELECT
+        timestamp,
+        SUM(CASE WHEN liability THEN 0 ELSE amount END) AS assets,
+        SUM(CASE WHEN liability THEN amount ELSE 0 END) AS liabilities,
+        SUM(CASE WHEN liability THEN -amount ELSE amount END) AS net_worth
+    FROM
+        pixiu_balance_history
+    WHERE
+        timestamp BETWEEN ? AND ?
+    GROUP BY timestamp
+    ORDER BY timestamp";
+    let rows = sqlx::query_as(sql)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows)
+}
+
+/// 总资产、总负债和净资产的折线图
+pub fn chart(history: &[NetWorth]) -> anyhow::Result<Vec<u8>> {
+    let keys = history
+        .iter()
+        .map(|item| utils::timestamp2time(item.timestamp, DATE_FORMAT))
+        .collect();
+    let series = |value: fn(&NetWorth) -> Decimal| {
+        history
+            .iter()
+            .map(|item| value(item).to_f32().unwrap_or_default())
+            .collect()
+    };
+    utils::create_lines_png(
+        "净资产",
+        keys,
+        vec![
+            ("总资产", series(|item| item.assets)),
+            ("总负债", series(|item| item.liabilities)),
+            ("净资产", series(|item| item.net_worth)),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_day_start_uses_shanghai_time() {
+        // 2024-01-01 00:30:00 +08:00，UTC 仍是 2023-12-31
+        assert_eq!(day_start(1704040200), 1704038400);
+        // 2024-01-01 23:55:00 +08:00
+        assert_eq!(day_start(1704124500), 1704038400);
+    }
+}
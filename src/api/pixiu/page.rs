@@ -0,0 +1,254 @@
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use sqlx::QueryBuilder;
+
+use super::{Db, FundInfo};
+use crate::{api::error::StatusError, storage::Money};
+
+/// 每页条数上限
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+/// 资金记录列表的排序字段，相同时按 id 从小到大
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    #[default]
+    Timestamp,
+    Amount,
+    Name,
+    Class,
+    Source,
+}
+
+impl SortField {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SortField::Timestamp => "timestamp",
+            SortField::Amount => "amount",
+            SortField::Name => "name",
+            SortField::Class => "class",
+            SortField::Source => "source",
+        }
+    }
+
+    fn value(&self, fund: &FundInfo) -> String {
+        match self {
+            SortField::Timestamp => fund.timestamp.to_string(),
+            SortField::Amount => fund.amount.to_string(),
+            SortField::Name => fund.name.clone(),
+            SortField::Class => fund.class.clone(),
+            SortField::Source => fund.source.clone(),
+        }
+    }
+
+    fn parse(&self, value: &str) -> Option<SortValue> {
+        match self {
+            SortField::Timestamp => value.parse().ok().map(SortValue::Int),
+            SortField::Amount => Decimal::from_str(value).ok().map(SortValue::Decimal),
+            _ => Some(SortValue::Text(value.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Order {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl Order {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Order::Asc => "asc",
+            Order::Desc => "desc",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SortValue {
+    Int(i64),
+    Decimal(Decimal),
+    Text(String),
+}
+
+impl SortValue {
+    fn push_bind(&self, qb: &mut QueryBuilder<'_, Db>) {
+        match self {
+            SortValue::Int(value) => qb.push_bind(*value),
+            SortValue::Decimal(value) => qb.push_bind(Money(*value)),
+            SortValue::Text(value) => qb.push_bind(value.clone()),
+        };
+    }
+}
+
+/// 从第几条开始：按页码跳过，或从游标指向的记录之后开始
+#[derive(Debug, Clone, PartialEq)]
+enum Start {
+    Offset(u32),
+    After { id: u32, value: SortValue },
+}
+
+/// 分页参数，游标分页不受翻页期间新增记录的影响
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    sort: SortField,
+    order: Order,
+    size: u32,
+    start: Start,
+}
+
+impl Page {
+    /// `page` 从 1 开始，与 `cursor` 只能指定一个，都不指定时为第一页
+    pub fn new(
+        page: Option<u32>,
+        size: u32,
+        cursor: Option<&str>,
+        sort: SortField,
+        order: Order,
+    ) -> anyhow::Result<Self> {
+        if size == 0 || size > MAX_PAGE_SIZE {
+            let message = format!("size must be between 1 and {MAX_PAGE_SIZE}");
+            return Err(StatusError::bad_request(message).into());
+        }
+        let start = match (page, cursor) {
+            (Some(_), Some(_)) => {
+                return Err(StatusError::bad_request("page and cursor are exclusive").into());
+            }
+            (Some(0), None) => return Err(StatusError::bad_request("page starts at 1").into()),
+            (Some(page), None) => Start::Offset(
+                (page - 1)
+                    .checked_mul(size)
+                    .ok_or_else(|| StatusError::bad_request("page is too large"))?,
+            ),
+            (None, Some(cursor)) => decode(cursor, sort, order)
+                .ok_or_else(|| StatusError::bad_request("invalid cursor"))?,
+            (None, None) => Start::Offset(0),
+        };
+        Ok(Page {
+            sort,
+            order,
+            size,
+            start,
+        })
+    }
+
+    /// 游标分页时追加 ` AND ...` 条件，只取游标之后的记录
+    pub(super) fn push_after(&self, qb: &mut QueryBuilder<'_, Db>) {
+        let Start::After { id, value } = &self.start else {
+            return;
+        };
+        let column = self.sort.as_str();
+        let op = match self.order {
+            Order::Asc => ">",
+            Order::Desc => "<",
+        };
+        qb.push(format!(" AND ({column} {op} "));
+        value.push_bind(qb);
+        qb.push(format!(" OR ({column} = "));
+        value.push_bind(qb);
+        qb.push(" AND id > ").push_bind(*id).push("))");
+    }
+
+    /// 追加 ` ORDER BY ... LIMIT ...`
+    pub(super) fn push_order(&self, qb: &mut QueryBuilder<'_, Db>) {
+        qb.push(format!(
+            " ORDER BY {} {}, id LIMIT ",
+            self.sort.as_str(),
+            self.order.as_str()
+        ))
+        .push_bind(self.size);
+        if let Start::Offset(offset) = self.start {
+            qb.push(" OFFSET ").push_bind(offset);
+        }
+    }
+
+    /// 下一页的游标，本页不满时没有下一页
+    pub fn next_cursor(&self, funds: &[FundInfo]) -> Option<String> {
+        if funds.len() < self.size as usize {
+            return None;
+        }
+        let last = funds.last()?;
+        let cursor = format!(
+            "{}:{}:{}:{}",
+            self.sort.as_str(),
+            self.order.as_str(),
+            last.id?,
+            self.sort.value(last)
+        );
+        Some(cursor.bytes().map(|byte| format!("{byte:02x}")).collect())
+    }
+}
+
+/// 解析十六进制编码的游标，排序方式必须与生成游标时一致
+fn decode(cursor: &str, sort: SortField, order: Order) -> Option<Start> {
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(cursor.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let cursor = String::from_utf8(bytes).ok()?;
+    let mut parts = cursor.splitn(4, ':');
+    if parts.next()? != sort.as_str() || parts.next()? != order.as_str() {
+        return None;
+    }
+    let id = parts.next()?.parse().ok()?;
+    let value = sort.parse(parts.next()?)?;
+    Some(Start::After { id, value })
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn fund(id: u32, name: &str) -> FundInfo {
+        FundInfo {
+            id: Some(id),
+            ..FundInfo::sample(dec!(-12.50), name)
+        }
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        for sort in [SortField::Timestamp, SortField::Amount, SortField::Name] {
+            let page = Page::new(None, 1, None, sort, Order::Asc).unwrap();
+            let cursor = page.next_cursor(&[fund(7, "a:b 午饭")]).unwrap();
+            let next = Page::new(None, 1, Some(&cursor), sort, Order::Asc).unwrap();
+            let value = sort.parse(&sort.value(&fund(7, "a:b 午饭"))).unwrap();
+            assert_eq!(next.start, Start::After { id: 7, value });
+            // 换了排序方式的游标无效
+            assert!(Page::new(None, 1, Some(&cursor), sort, Order::Desc).is_err());
+        }
+        assert!(Page::new(None, 1, Some("zz"), SortField::Name, Order::Asc).is_err());
+    }
+
+    #[test]
+    fn test_page_is_validated() {
+        let page = |page, size| Page::new(page, size, None, SortField::Timestamp, Order::Desc);
+        assert!(page(Some(0), 20).is_err());
+        assert!(page(Some(1), 0).is_err());
+        assert!(page(Some(1), MAX_PAGE_SIZE + 1).is_err());
+        assert!(page(Some(u32::MAX), 20).is_err());
+        assert_eq!(page(Some(3), 20).unwrap().start, Start::Offset(40));
+        assert_eq!(page(None, 20).unwrap().start, Start::Offset(0));
+    }
+
+    #[test]
+    fn test_keyset_sql() {
+        let first = Page::new(None, 2, None, SortField::Amount, Order::Desc).unwrap();
+        let cursor = first.next_cursor(&[fund(1, "a"), fund(2, "b")]).unwrap();
+        let page = Page::new(None, 2, Some(&cursor), SortField::Amount, Order::Desc).unwrap();
+        let mut qb = QueryBuilder::new("SELECT * FROM pixiu_fund_info WHERE 1");
+        page.push_after(&mut qb);
+        page.push_order(&mut qb);
+        assert_eq!(
+            qb.into_sql(),
+            "SELECT * FROM pixiu_fund_info WHERE 1 AND (amount < ? OR (amount = ? AND id > ?)) \
+            ORDER BY amount desc, id LIMIT ?"
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use rust_decimal::Decimal;
+use sqlx::{Pool, Transaction};
+
+use super::{currency, Db, FundInfo, DIALECT};
+use crate::{
+    api::error::StatusError,
+    storage::{InsertId, Money},
+};
+
+/// 余额断言：某个资产账户在某一时刻的实际余额（银行、支付宝等显示的余额）
+///
+/// 与账本余额一致时，该账户在此时刻及之前的资金记录标记为已对账，不能再修改或删除
+#[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
+pub struct BalanceAssertion {
+    id: Option<u32>,
+    source: String,
+    timestamp: i64,
+    /// 实际余额，以账户币种计
+    #[sqlx(try_from = "Money")]
+    amount: Decimal,
+    /// 断言时由期初余额、资金记录和转账算出的账本余额
+    #[serde(default)]
+    #[sqlx(try_from = "Money")]
+    ledger_amount: Decimal,
+    /// 实际余额减账本余额
+    #[sqlx(default, try_from = "Money")]
+    #[serde(default)]
+    difference: Decimal,
+}
+
+/// 账户在 `at` 时刻（含）的账本余额，以账户币种计，算法与 `get_property_info` 相同
+async fn balance_at(
+    tx: &mut Transaction<'_, Db>,
+    source: &str,
+    at: i64,
+) -> anyhow::Result<Decimal> {
+    let sql = "SELECT ppi.opening_timestamp,
+        (ppi.opening_balance
+            + COALESCE((SELECT SUM(pfi.amount) FROM pixiu_fund_info pfi
+                WHERE pfi.source = ppi.name AND pfi.currency = ppi.currency
+                    AND pfi.timestamp > ppi.opening_timestamp AND pfi.timestamp <= ?
+                    AND pfi.deleted_at IS NULL), 0)
+            + COALESCE((SELECT SUM(COALESCE(pt.to_amount, pt.amount)) FROM pixiu_transfer pt
+                WHERE pt.to_source = ppi.name AND pt.timestamp > ppi.opening_timestamp
+                    AND pt.timestamp <= ?), 0)
+            - COALESCE((SELECT SUM(pt.amount + pt.fee) FROM pixiu_transfer pt
+                WHERE pt.from_source = ppi.name AND pt.timestamp > ppi.opening_timestamp
+                    AND pt.timestamp <= ?), 0)
+        )
+    FROM pixiu_property_info ppi WHERE ppi.name = ?";
+    let sql = format!("{sql}{}", DIALECT.for_update());
+    let row: Option<(i64, Money)> = sqlx::query_as(&sql)
+        .bind(at)
+        .bind(at)
+        .bind(at)
+        .bind(source)
+        .fetch_optional(&mut **tx)
+        .await?;
+    let Some((opening_timestamp, Money(balance))) = row else {
+        return Err(StatusError::not_found(format!("property {source} not found")).into());
+    };
+    if at < opening_timestamp {
+        let message = format!("{source} opened at {opening_timestamp}, after {at}");
+        return Err(StatusError::bad_request(message).into());
+    }
+    let foreign = currency::foreign_fund_sums(tx, Some(source), at).await?;
+    Ok(balance + foreign.get(source).copied().unwrap_or_default())
+}
+
+/// 账户已对账到的时刻，即最近一次余额一致的断言时间
+async fn reconciled_until(
+    tx: &mut Transaction<'_, Db>,
+    source: &str,
+) -> anyhow::Result<Option<i64>> {
+    let sql = "SELECT MAX(timestamp) FROM pixiu_balance_assertion
+        WHERE source = ? AND amount = ledger_amount";
+    let until = sqlx::query_scalar(sql)
+        .bind(source)
+        .fetch_one(&mut **tx)
+        .await?;
+    Ok(until)
+}
+
+fn check_period(until: Option<i64>, source: &str, timestamp: i64) -> anyhow::Result<()> {
+    match until {
+        Some(until) if timestamp <= until => {
+            let message = format!("{source} is reconciled up to {until}");
+            Err(StatusError::conflict(message).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// 新增或改动后的资金记录不能落在已对账的时间段内
+pub(super) async fn check_open(
+    tx: &mut Transaction<'_, Db>,
+    source: &str,
+    timestamp: i64,
+) -> anyhow::Result<()> {
+    let until = reconciled_until(tx, source).await?;
+    check_period(until, source, timestamp)
+}
+
+/// 已对账的资金记录不能修改或删除
+pub(super) fn check_unlocked(fund: &FundInfo) -> anyhow::Result<()> {
+    if fund.reconciled {
+        let message = format!("fund {} is reconciled", fund.id.unwrap_or_default());
+        return Err(StatusError::conflict(message).into());
+    }
+    Ok(())
+}
+
+/// 余额断言及当时的差额，可只看某个账户
+pub async fn get_assertions(
+    pool: &Pool<Db>,
+    source: Option<String>,
+) -> anyhow::Result<Vec<BalanceAssertion>> {
+    let sql = "SELECT *, amount - ledger_amount AS difference FROM pixiu_balance_assertion
+        WHERE ? IS NULL OR source = ? ORDER BY timestamp DESC, id DESC";
+    let rows = sqlx::query_as(sql)
+        .bind(&source)
+        .bind(&source)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows)
+}
+
+/// 记录余额断言并计算差额，余额一致时把该时刻及之前的资金记录标记为已对账
+pub async fn insert_assertion(
+    pool: &Pool<Db>,
+    mut info: BalanceAssertion,
+) -> anyhow::Result<BalanceAssertion> {
+    let mut tx = pool.begin().await?;
+    info.ledger_amount = balance_at(&mut tx, &info.source, info.timestamp).await?;
+    info.difference = info.amount - info.ledger_amount;
+    let sql = "INSERT INTO pixiu_balance_assertion (source, timestamp, amount, ledger_amount)
+        VALUES (?, ?, ?, ?)";
+    let result = sqlx::query(sql)
+        .bind(&info.source)
+        .bind(info.timestamp)
+        .bind(Money(info.amount))
+        .bind(Money(info.ledger_amount))
+        .execute(&mut *tx)
+        .await?;
+    info.id = Some(result.insert_id() as u32);
+    if info.difference.is_zero() {
+        let sql = "UPDATE pixiu_fund_info SET reconciled = TRUE
+            WHERE source = ? AND timestamp <= ? AND deleted_at IS NULL";
+        sqlx::query(sql)
+            .bind(&info.source)
+            .bind(info.timestamp)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(info)
+}
+
+/// 删除余额断言，按剩余的断言重新标记对账状态
+pub async fn delete_assertion(pool: &Pool<Db>, id: u32) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    let sql = format!(
+        "SELECT source FROM pixiu_balance_assertion WHERE id = ?{}",
+        DIALECT.for_update()
+    );
+    let source: Option<String> = sqlx::query_scalar(&sql)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some(source) = source else {
+        return Err(StatusError::not_found(format!("assertion {id} not found")).into());
+    };
+    let sql = "DELETE FROM pixiu_balance_assertion WHERE id = ?";
+    sqlx::query(sql).bind(id).execute(&mut *tx).await?;
+    let until = reconciled_until(&mut tx, &source).await?;
+    let sql = "UPDATE pixiu_fund_info SET reconciled = (timestamp <= ? AND deleted_at IS NULL)
+        WHERE source = ?";
+    sqlx::query(sql)
+        .bind(until.unwrap_or(i64::MIN))
+        .bind(&source)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn test_reconciled_period_is_closed() {
+        assert!(check_period(None, "招商银行", 100).is_ok());
+        assert!(check_period(Some(100), "招商银行", 101).is_ok());
+        let err = check_period(Some(100), "招商银行", 100).unwrap_err();
+        let StatusError(status, _) = err.downcast_ref::<StatusError>().unwrap();
+        assert_eq!(*status, axum::http::StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn test_reconciled_fund_is_locked() {
+        let mut fund = FundInfo::sample(dec!(-25), "午饭");
+        assert!(check_unlocked(&fund).is_ok());
+        fund.reconciled = true;
+        let err = check_unlocked(&fund).unwrap_err();
+        let StatusError(status, _) = err.downcast_ref::<StatusError>().unwrap();
+        assert_eq!(*status, axum::http::StatusCode::CONFLICT);
+    }
+}
@@ -0,0 +1,559 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, TimeZone};
+use chrono_tz::{Asia::Shanghai, Tz};
+use log::{info, warn};
+use rust_decimal::Decimal;
+use sqlx::{Executor, Pool};
+
+use super::{budget, insert_fund, rule, Db, FundInfo};
+use crate::{
+    api::error::StatusError,
+    storage::{InsertId, Money, NullableMoney},
+};
+
+/// 定时任务生成的记录在审计日志中的客户端
+const AUDIT_CLIENT: &str = "recurring";
+/// 每周规则的最大间隔周数
+const MAX_WEEKS: u32 = 52;
+/// 一次最多计算的发生次数，查询超过时返回 400，定时任务超过时下次继续生成
+pub const MAX_OCCURRENCES: usize = 10_000;
+
+/// 重复规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    /// 每月第 `rule_value` 天，超出当月天数时取月末
+    Monthly,
+    /// 每 `rule_value` 周
+    Weekly,
+}
+
+impl Rule {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Rule::Monthly => "monthly",
+            Rule::Weekly => "weekly",
+        }
+    }
+}
+
+impl TryFrom<String> for Rule {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "monthly" => Ok(Rule::Monthly),
+            "weekly" => Ok(Rule::Weekly),
+            _ => anyhow::bail!("unknown recurrence rule: {value}"),
+        }
+    }
+}
+
+/// 周期记账模板，由定时任务生成资金记录
+#[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
+pub struct RecurringInfo {
+    id: Option<u32>,
+    #[sqlx(try_from = "Money")]
+    amount: Decimal,
+    name: String,
+    class: String,
+    source: String,
+    #[sqlx(try_from = "String")]
+    rule: Rule,
+    rule_value: u32,
+    /// 首次发生时间，同时决定每次发生的时刻
+    start_timestamp: i64,
+    end_timestamp: Option<i64>,
+    /// 最近一次已生成的发生时间
+    #[serde(default)]
+    last_posted: Option<i64>,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// 对某一次发生的调整：跳过，或替换部分字段
+#[derive(sqlx::FromRow, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct OccurrenceOverride {
+    #[serde(default)]
+    skip: bool,
+    #[sqlx(try_from = "NullableMoney")]
+    amount: Option<Decimal>,
+    name: Option<String>,
+    class: Option<String>,
+    source: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct OverrideRow {
+    occurrence: i64,
+    #[sqlx(flatten)]
+    adjust: OccurrenceOverride,
+}
+
+/// 一次发生及其生效的记账内容
+#[derive(Debug, serde::Serialize)]
+pub struct Occurrence {
+    timestamp: i64,
+    posted: bool,
+    skip: bool,
+    amount: Decimal,
+    name: String,
+    class: String,
+    source: String,
+}
+
+impl RecurringInfo {
+    fn validate(&self) -> Result<(), StatusError> {
+        let valid = match self.rule {
+            Rule::Monthly => (1..=31).contains(&self.rule_value),
+            Rule::Weekly => (1..=MAX_WEEKS).contains(&self.rule_value),
+        };
+        if !valid {
+            return Err(StatusError::bad_request(format!(
+                "invalid {} value: {}",
+                self.rule.as_str(),
+                self.rule_value
+            )));
+        }
+        if self.start().is_none() {
+            return Err(StatusError::bad_request("start_timestamp is out of range"));
+        }
+        Ok(())
+    }
+
+    fn start(&self) -> Option<DateTime<Tz>> {
+        Shanghai.timestamp_opt(self.start_timestamp, 0).single()
+    }
+
+    /// 第 `n` 次（从 0 开始）的发生时间，按月时可能早于首次时间
+    fn nth(&self, start: DateTime<Tz>, n: u32) -> Option<DateTime<Tz>> {
+        match self.rule {
+            Rule::Monthly => {
+                let month = NaiveDate::from_ymd_opt(start.year(), start.month(), 1)?
+                    .checked_add_months(Months::new(n))?;
+                let last_day = (month + Months::new(1)).pred_opt()?.day();
+                let date = month.with_day(self.rule_value.min(last_day))?;
+                Shanghai
+                    .from_local_datetime(&date.and_time(start.time()))
+                    .earliest()
+            }
+            Rule::Weekly => {
+                let weeks = n.checked_mul(self.rule_value)?;
+                start.checked_add_signed(Duration::try_weeks(weeks.into())?)
+            }
+        }
+    }
+
+    /// 不晚于 `after` 的最后一次发生的序号，从这里开始计算可以跳过之前的发生
+    fn first_index(&self, start: DateTime<Tz>, after: Option<i64>) -> u32 {
+        let Some(after) = after.filter(|after| *after > start.timestamp()) else {
+            return 0;
+        };
+        match self.rule {
+            Rule::Monthly => {
+                let Some(after) = Shanghai.timestamp_opt(after, 0).single() else {
+                    return u32::MAX;
+                };
+                let months = (i64::from(after.year()) - i64::from(start.year())) * 12
+                    + i64::from(after.month())
+                    - i64::from(start.month());
+                u32::try_from(months).unwrap_or(u32::MAX)
+            }
+            Rule::Weekly => {
+                let period = Duration::weeks(1).num_seconds() * i64::from(self.rule_value);
+                let weeks = (after - start.timestamp()).checked_div(period).unwrap_or(0);
+                u32::try_from(weeks).unwrap_or(u32::MAX)
+            }
+        }
+    }
+
+    /// `(after, until]` 之间最早的至多 `limit` 次发生时间
+    fn occurrences(&self, after: Option<i64>, until: i64, limit: usize) -> Vec<i64> {
+        let Some(start) = self.start() else {
+            return vec![];
+        };
+        let until = self.end_timestamp.map_or(until, |end| end.min(until));
+        let mut occurrences = vec![];
+        for n in self.first_index(start, after)..=u32::MAX {
+            if occurrences.len() == limit {
+                break;
+            }
+            let Some(time) = self.nth(start, n).map(|time| time.timestamp()) else {
+                break;
+            };
+            if time > until {
+                break;
+            }
+            if time >= self.start_timestamp && after.is_none_or(|after| time > after) {
+                occurrences.push(time);
+            }
+        }
+        occurrences
+    }
+
+    /// 应用调整后的记账内容，跳过时返回 None
+    fn fund(&self, timestamp: i64, adjust: Option<&OccurrenceOverride>) -> Option<FundInfo> {
+        let adjust = match adjust {
+            Some(adjust) if adjust.skip => return None,
+            Some(adjust) => adjust,
+            None => &OccurrenceOverride::default(),
+        };
+        Some(FundInfo {
+            id: None,
+            amount: adjust.amount.unwrap_or(self.amount),
+            name: adjust.name.clone().unwrap_or_else(|| self.name.clone()),
+            class: adjust.class.clone().unwrap_or_else(|| self.class.clone()),
+            timestamp,
+            source: adjust.source.clone().unwrap_or_else(|| self.source.clone()),
+            currency: String::new(),
+            tags: vec![],
+            splits: vec![],
+            reconciled: false,
+        })
+    }
+}
+
+pub async fn get_recurring(pool: &Pool<Db>) -> anyhow::Result<Vec<RecurringInfo>> {
+    let sql = "SELECT * FROM pixiu_recurring ORDER BY id";
+    let rows = sqlx::query_as(sql).fetch_all(pool).await?;
+    Ok(rows)
+}
+
+async fn get_recurring_by_id(pool: &Pool<Db>, id: u32) -> anyhow::Result<RecurringInfo> {
+    let sql = "SELECT * FROM pixiu_recurring WHERE id = ?";
+    let row: Option<RecurringInfo> = sqlx::query_as(sql).bind(id).fetch_optional(pool).await?;
+    row.ok_or_else(|| StatusError::not_found(format!("recurring {id} not found")).into())
+}
+
+pub async fn insert_recurring(pool: &Pool<Db>, info: RecurringInfo) -> anyhow::Result<u64> {
+    info.validate()?;
+    let sql = "INSERT INTO pixiu_recurring (amount, name, class, source, rule, rule_value,
+        start_timestamp, end_timestamp, enabled) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)";
+    let result = sqlx::query(sql)
+        .bind(Money(info.amount))
+        .bind(info.name)
+        .bind(info.class)
+        .bind(info.source)
+        .bind(info.rule.as_str())
+        .bind(info.rule_value)
+        .bind(info.start_timestamp)
+        .bind(info.end_timestamp)
+        .bind(info.enabled)
+        .execute(pool)
+        .await?;
+    Ok(result.insert_id())
+}
+
+/// 更新模板，已生成的记录不受影响
+pub async fn update_recurring(pool: &Pool<Db>, id: u32, info: RecurringInfo) -> anyhow::Result<()> {
+    info.validate()?;
+    let sql = "UPDATE pixiu_recurring SET amount = ?, name = ?, class = ?, source = ?, rule = ?,
+        rule_value = ?, start_timestamp = ?, end_timestamp = ?, enabled = ? WHERE id = ?";
+    sqlx::query(sql)
+        .bind(Money(info.amount))
+        .bind(info.name)
+        .bind(info.class)
+        .bind(info.source)
+        .bind(info.rule.as_str())
+        .bind(info.rule_value)
+        .bind(info.start_timestamp)
+        .bind(info.end_timestamp)
+        .bind(info.enabled)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_recurring(pool: &Pool<Db>, id: u32) -> anyhow::Result<()> {
+    let sql = "DELETE FROM pixiu_recurring WHERE id = ?";
+    sqlx::query(sql).bind(id).execute(pool).await?;
+    Ok(())
+}
+
+/// 模板在 `(after, until]` 之间各次发生的调整
+async fn get_overrides<'e>(
+    executor: impl Executor<'e, Database = Db>,
+    id: u32,
+    after: i64,
+    until: i64,
+) -> anyhow::Result<HashMap<i64, OccurrenceOverride>> {
+    let sql = "SELECT occurrence, skip, amount, name, class, source FROM pixiu_recurring_override
+        WHERE recurring_id = ? AND occurrence > ? AND occurrence <= ?";
+    let rows: Vec<OverrideRow> = sqlx::query_as(sql)
+        .bind(id)
+        .bind(after)
+        .bind(until)
+        .fetch_all(executor)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.occurrence, row.adjust))
+        .collect())
+}
+
+/// `from..=to` 之间的发生时间及生效内容，超过 `MAX_OCCURRENCES` 次时返回 400
+pub async fn get_occurrences(
+    pool: &Pool<Db>,
+    id: u32,
+    from: i64,
+    to: i64,
+) -> anyhow::Result<Vec<Occurrence>> {
+    let recurring = get_recurring_by_id(pool, id).await?;
+    let after = from.saturating_sub(1);
+    let timestamps = recurring.occurrences(Some(after), to, MAX_OCCURRENCES + 1);
+    if timestamps.len() > MAX_OCCURRENCES {
+        let message = format!("at most {MAX_OCCURRENCES} occurrences, narrow the range");
+        return Err(StatusError::bad_request(message).into());
+    }
+    let overrides = get_overrides(pool, id, after, to).await?;
+    let mut occurrences = vec![];
+    for timestamp in timestamps {
+        let adjust = overrides.get(&timestamp);
+        let posted = recurring.last_posted.is_some_and(|last| timestamp <= last);
+        let fund = recurring.fund(timestamp, adjust);
+        let skip = fund.is_none();
+        let fund = fund.unwrap_or_else(|| recurring.fund(timestamp, None).unwrap());
+        occurrences.push(Occurrence {
+            timestamp,
+            posted,
+            skip,
+            amount: fund.amount,
+            name: fund.name,
+            class: fund.class,
+            source: fund.source,
+        });
+    }
+    Ok(occurrences)
+}
+
+/// 跳过或修改某一次发生，只对尚未生成的发生有效
+pub async fn set_override(
+    pool: &Pool<Db>,
+    id: u32,
+    occurrence: i64,
+    adjust: OccurrenceOverride,
+) -> anyhow::Result<()> {
+    let recurring = get_recurring_by_id(pool, id).await?;
+    if !recurring
+        .occurrences(Some(occurrence.saturating_sub(1)), occurrence, 1)
+        .contains(&occurrence)
+    {
+        return Err(StatusError::bad_request(format!("{occurrence} is not an occurrence")).into());
+    }
+    if recurring.last_posted.is_some_and(|last| occurrence <= last) {
+        return Err(StatusError::conflict(format!("{occurrence} is already posted")).into());
+    }
+    let sql = "REPLACE INTO pixiu_recurring_override
+        (recurring_id, occurrence, skip, amount, name, class, source) VALUES (?, ?, ?, ?, ?, ?, ?)";
+    sqlx::query(sql)
+        .bind(id)
+        .bind(occurrence)
+        .bind(adjust.skip)
+        .bind(adjust.amount.map(Money))
+        .bind(adjust.name)
+        .bind(adjust.class)
+        .bind(adjust.source)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_override(pool: &Pool<Db>, id: u32, occurrence: i64) -> anyhow::Result<()> {
+    let sql = "DELETE FROM pixiu_recurring_override WHERE recurring_id = ? AND occurrence = ?";
+    sqlx::query(sql)
+        .bind(id)
+        .bind(occurrence)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// 生成所有启用模板截至 `now` 的资金记录，返回生成条数
+///
+/// 与单条新增一样应用自动分类规则并检查对账，某个模板的记录落在已对账的时间段内时
+/// 跳过该模板并记录日志，其余模板照常生成
+pub async fn post_due(pool: &Pool<Db>, now: i64) -> anyhow::Result<usize> {
+    let sql = "SELECT * FROM pixiu_recurring WHERE enabled";
+    let templates: Vec<RecurringInfo> = sqlx::query_as(sql).fetch_all(pool).await?;
+    let rules = rule::Rules::load(pool).await?;
+    let mut posted = vec![];
+    'templates: for recurring in templates {
+        let occurrences = recurring.occurrences(recurring.last_posted, now, MAX_OCCURRENCES);
+        let (Some(id), Some(&first), Some(&last)) =
+            (recurring.id, occurrences.first(), occurrences.last())
+        else {
+            continue;
+        };
+        // 生成记录与更新进度在同一事务中，避免重复生成
+        let mut tx = pool.begin().await?;
+        let overrides = get_overrides(&mut *tx, id, first.saturating_sub(1), last).await?;
+        let mut funds = vec![];
+        for timestamp in occurrences {
+            let Some(mut fund) = recurring.fund(timestamp, overrides.get(&timestamp)) else {
+                continue;
+            };
+            rules.apply(&mut fund);
+            if let Err(err) = insert_fund(&mut tx, &fund, AUDIT_CLIENT).await {
+                if err.downcast_ref::<StatusError>().is_none() {
+                    return Err(err);
+                }
+                warn!("recurring {id} not posted: {err:#}");
+                continue 'templates;
+            }
+            funds.push(fund);
+        }
+        let sql = "UPDATE pixiu_recurring SET last_posted = ? WHERE id = ?";
+        sqlx::query(sql)
+            .bind(last)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        posted.extend(funds);
+    }
+    info!("posted {} recurring funds", posted.len());
+    let count = posted.len();
+    budget::spawn_notify(pool, posted);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn recurring(rule: Rule, rule_value: u32, start_timestamp: i64) -> RecurringInfo {
+        RecurringInfo {
+            id: Some(1),
+            amount: dec!(-3000),
+            name: "房租".to_string(),
+            class: "住房".to_string(),
+            source: "招商银行".to_string(),
+            rule,
+            rule_value,
+            start_timestamp,
+            end_timestamp: None,
+            last_posted: None,
+            enabled: true,
+        }
+    }
+
+    fn time(s: &str) -> i64 {
+        let time = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        Shanghai.from_local_datetime(&time).unwrap().timestamp()
+    }
+
+    #[test]
+    fn test_monthly_clamps_to_month_end() {
+        let rent = recurring(Rule::Monthly, 31, time("2024-01-10 09:00"));
+        assert_eq!(
+            rent.occurrences(None, time("2024-04-30 09:00"), MAX_OCCURRENCES),
+            vec![
+                time("2024-01-31 09:00"),
+                time("2024-02-29 09:00"),
+                time("2024-03-31 09:00"),
+                time("2024-04-30 09:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_monthly_skips_days_before_start() {
+        let salary = recurring(Rule::Monthly, 5, time("2024-01-10 09:00"));
+        assert_eq!(
+            salary.occurrences(None, time("2024-03-01 00:00"), MAX_OCCURRENCES),
+            vec![time("2024-02-05 09:00")]
+        );
+    }
+
+    #[test]
+    fn test_weekly_after_last_posted() {
+        let mut weekly = recurring(Rule::Weekly, 2, time("2024-01-01 08:00"));
+        weekly.end_timestamp = Some(time("2024-02-12 08:00"));
+        assert_eq!(
+            weekly.occurrences(
+                Some(time("2024-01-15 08:00")),
+                time("2024-12-31 00:00"),
+                MAX_OCCURRENCES
+            ),
+            vec![time("2024-01-29 08:00"), time("2024-02-12 08:00"),]
+        );
+    }
+
+    #[test]
+    fn test_override() {
+        let rent = recurring(Rule::Monthly, 1, time("2024-01-01 09:00"));
+        let skip = OccurrenceOverride {
+            skip: true,
+            ..Default::default()
+        };
+        assert!(rent.fund(0, Some(&skip)).is_none());
+        let change = OccurrenceOverride {
+            amount: Some(dec!(-3200)),
+            ..Default::default()
+        };
+        let fund = rent.fund(0, Some(&change)).unwrap();
+        assert_eq!(fund.amount, dec!(-3200));
+        assert_eq!(fund.name, "房租");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(recurring(Rule::Monthly, 0, 0).validate().is_err());
+        assert!(recurring(Rule::Monthly, 32, 0).validate().is_err());
+        assert!(recurring(Rule::Weekly, 0, 0).validate().is_err());
+        assert!(recurring(Rule::Weekly, 3, 0).validate().is_ok());
+        assert!(recurring(Rule::Weekly, MAX_WEEKS + 1, 0)
+            .validate()
+            .is_err());
+        assert!(recurring(Rule::Weekly, u32::MAX, 0).validate().is_err());
+        assert!(recurring(Rule::Monthly, 1, i64::MAX).validate().is_err());
+    }
+
+    #[test]
+    fn test_weekly_stops_at_date_limit() {
+        let start = time("2024-01-01 08:00");
+        let weekly = recurring(Rule::Weekly, MAX_WEEKS, start);
+        let first = weekly.occurrences(None, i64::MAX, MAX_OCCURRENCES);
+        assert_eq!(first.len(), MAX_OCCURRENCES);
+        assert_eq!(first.first(), Some(&start));
+        assert!(first.windows(2).all(|pair| pair[0] < pair[1]));
+        // 从上次生成之后继续，直到日期上限
+        let last = weekly.occurrences(Some(i64::MAX / 2), i64::MAX, MAX_OCCURRENCES);
+        assert!(last.is_empty());
+        let max = NaiveDate::MAX
+            .and_time(chrono::NaiveTime::MIN)
+            .and_utc()
+            .timestamp();
+        let last = weekly.occurrences(Some(max - 400 * 86400), i64::MAX, MAX_OCCURRENCES);
+        assert!(!last.is_empty() && last.len() <= 2);
+        assert!(recurring(Rule::Weekly, 1, i64::MAX)
+            .occurrences(None, i64::MAX, MAX_OCCURRENCES)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_occurrences_start_after_last_posted() {
+        let start = time("2024-01-31 09:00");
+        let rent = recurring(Rule::Monthly, 31, start);
+        let after = time("2024-03-31 09:00");
+        assert_eq!(
+            rent.occurrences(Some(after), time("2024-05-31 09:00"), MAX_OCCURRENCES),
+            vec![time("2024-04-30 09:00"), time("2024-05-31 09:00")]
+        );
+        assert_eq!(rent.occurrences(Some(after - 1), i64::MAX, 1), vec![after]);
+        assert_eq!(rent.occurrences(Some(i64::MIN), start, 1), vec![start]);
+        let weekly = recurring(Rule::Weekly, 1, start);
+        assert_eq!(
+            weekly.occurrences(Some(start + 7 * 86400), i64::MAX, 1),
+            vec![start + 14 * 86400]
+        );
+    }
+}
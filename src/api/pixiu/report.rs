@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Datelike, Days, Months, NaiveDate, TimeZone};
+use chrono_tz::Asia::Shanghai;
+use rust_decimal::Decimal;
+use sqlx::{Pool, QueryBuilder};
+
+use super::{currency, split::FUND_LINES_SQL, Db, FundFilter, DIALECT};
+use crate::{api::error::StatusError, storage::Money};
+
+/// 上海时区相对 UTC 的偏移（秒），1991 年后无夏令时
+const SHANGHAI_OFFSET: i64 = 8 * 60 * 60;
+const DAY: i64 = 24 * 60 * 60;
+/// 一次报表最多的条数，即周期数乘以拆分项数
+pub const MAX_ITEMS: usize = 10_000;
+
+/// 统计周期
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    Day,
+    /// 自然周，从周一开始
+    Week,
+    Month,
+    Year,
+}
+
+/// 拆分维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Split {
+    Class,
+    Source,
+}
+
+impl Split {
+    fn column(&self) -> &'static str {
+        match self {
+            Split::Class => "class",
+            Split::Source => "source",
+        }
+    }
+}
+
+/// 一个周期（及拆分项）的收支，支出为负数
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct ReportItem {
+    period: String,
+    /// 周期开始时间
+    timestamp: i64,
+    key: Option<String>,
+    income: Decimal,
+    expense: Decimal,
+    net: Decimal,
+}
+
+impl Period {
+    /// 日期所在周期的第一天
+    fn start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Day => date,
+            Period::Week => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            Period::Month => date.with_day(1).unwrap(),
+            Period::Year => date.with_ordinal(1).unwrap(),
+        }
+    }
+
+    /// 下一个周期的第一天，超出日期范围时为空
+    fn next(&self, start: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Period::Day => start.checked_add_days(Days::new(1)),
+            Period::Week => start.checked_add_days(Days::new(7)),
+            Period::Month => start.checked_add_months(Months::new(1)),
+            Period::Year => start.checked_add_months(Months::new(12)),
+        }
+    }
+
+    fn label(&self, start: NaiveDate) -> String {
+        let format = match self {
+            Period::Day | Period::Week => "%Y-%m-%d",
+            Period::Month => "%Y-%m",
+            Period::Year => "%Y",
+        };
+        start.format(format).to_string()
+    }
+}
+
+/// 时间戳在上海时区的日期，超出日期范围时为空
+fn date(timestamp: i64) -> Option<NaiveDate> {
+    Shanghai
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|time| time.date_naive())
+}
+
+fn day_to_date(day: i64) -> Option<NaiveDate> {
+    date(day.checked_mul(DAY)? - SHANGHAI_OFFSET)
+}
+
+/// 日期在上海时区的零点，早年夏令时跳过零点时按固定偏移计算
+fn timestamp(date: NaiveDate) -> i64 {
+    let midnight = date.and_time(chrono::NaiveTime::MIN);
+    Shanghai
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map_or(midnight.and_utc().timestamp() - SHANGHAI_OFFSET, |time| {
+            time.timestamp()
+        })
+}
+
+/// `from..=to` 覆盖的各周期的第一天，范围无效或周期过多时返回 400
+fn periods(period: Period, (from, to): (i64, i64)) -> anyhow::Result<Vec<NaiveDate>> {
+    if from > to {
+        return Err(StatusError::bad_request("from must not be after to").into());
+    }
+    let (Some(from), Some(end)) = (date(from), date(to)) else {
+        return Err(StatusError::bad_request("from or to is out of range").into());
+    };
+    let mut start = Some(period.start(from));
+    let mut starts = vec![];
+    while let Some(current) = start.filter(|start| *start <= end) {
+        if starts.len() == MAX_ITEMS {
+            let message = format!("at most {MAX_ITEMS} periods per report, use a longer period");
+            return Err(StatusError::bad_request(message).into());
+        }
+        starts.push(current);
+        start = period.next(current);
+    }
+    Ok(starts)
+}
+
+/// 将按天汇总的收支归入周期，范围内没有记录的周期补零
+fn bucket(
+    period: Period,
+    starts: &[NaiveDate],
+    days: Vec<(i64, Option<String>, Decimal, Decimal)>,
+) -> anyhow::Result<Vec<ReportItem>> {
+    let mut keys = BTreeSet::new();
+    let mut sums: BTreeMap<(NaiveDate, Option<String>), (Decimal, Decimal)> = BTreeMap::new();
+    for (day, key, income, expense) in days {
+        let Some(date) = day_to_date(day) else {
+            continue;
+        };
+        keys.insert(key.clone());
+        let sum = sums.entry((period.start(date), key)).or_default();
+        sum.0 += income;
+        sum.1 += expense;
+    }
+    if keys.is_empty() {
+        keys.insert(None);
+    }
+    if starts.len().saturating_mul(keys.len()) > MAX_ITEMS {
+        let message = format!("at most {MAX_ITEMS} report items, narrow the range or the split");
+        return Err(StatusError::bad_request(message).into());
+    }
+    let mut items = vec![];
+    for &start in starts {
+        for key in &keys {
+            let (income, expense) = sums.get(&(start, key.clone())).copied().unwrap_or_default();
+            items.push(ReportItem {
+                period: period.label(start),
+                timestamp: timestamp(start),
+                key: key.clone(),
+                income,
+                expense,
+                net: income + expense,
+            });
+        }
+    }
+    Ok(items)
+}
+
+/// 按周期汇总收入、支出和结余，可按分类或来源拆分，金额折合为 `base` 币种
+pub async fn get_report(
+    pool: &Pool<Db>,
+    filter: &FundFilter,
+    period: Period,
+    split: Option<Split>,
+    base: &str,
+) -> anyhow::Result<Vec<ReportItem>> {
+    let starts = periods(period, (filter.from, filter.to))?;
+    let key = split.map_or("NULL", |split| split.column());
+    // 先在数据库中按上海时区的自然日汇总，再在内存中归入周期
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT (timestamp + {SHANGHAI_OFFSET}) {} {DAY} AS day, {key} AS split_key, currency,
+            SUM(CASE WHEN amount > 0 THEN amount ELSE 0 END),
+            SUM(CASE WHEN amount < 0 THEN amount ELSE 0 END), ",
+        DIALECT.int_div()
+    ));
+    currency::push_rate_time(&mut qb, base);
+    qb.push(format!(" FROM {FUND_LINES_SQL}"));
+    filter.push_line_where(&mut qb);
+    qb.push(" GROUP BY day, split_key, currency, rate_time");
+    let rows: Vec<(i64, Option<String>, String, Money, Money, i64)> =
+        qb.build_query_as().fetch_all(pool).await?;
+    let rates = currency::Rates::load(pool).await?;
+    let mut days = Vec::with_capacity(rows.len());
+    for (day, key, currency, income, expense, rate_time) in rows {
+        let income = rates.convert(income.0, &currency, base, rate_time)?;
+        let expense = rates.convert(expense.0, &currency, base, rate_time)?;
+        days.push((day, key, income, expense));
+    }
+    bucket(period, &starts, days)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn day(y: i32, m: u32, d: u32) -> i64 {
+        (timestamp(NaiveDate::from_ymd_opt(y, m, d).unwrap()) + SHANGHAI_OFFSET) / DAY
+    }
+
+    #[test]
+    fn test_day_number_uses_shanghai_time() {
+        // 2024-01-01 00:30:00 +08:00，UTC 仍是 2023-12-31
+        let timestamp = 1704040200;
+        assert_eq!((timestamp + SHANGHAI_OFFSET) / DAY, day(2024, 1, 1));
+        assert_eq!(
+            day_to_date(day(2024, 1, 1)),
+            NaiveDate::from_ymd_opt(2024, 1, 1)
+        );
+    }
+
+    #[test]
+    fn test_week_starts_on_monday() {
+        // 2024-01-07 为周日
+        let sunday = NaiveDate::from_ymd_opt(2024, 1, 7).unwrap();
+        assert_eq!(Period::Week.label(Period::Week.start(sunday)), "2024-01-01");
+    }
+
+    #[test]
+    fn test_bucket_by_month_fills_gaps() {
+        let from = timestamp(NaiveDate::from_ymd_opt(2024, 1, 15).unwrap());
+        let to = timestamp(NaiveDate::from_ymd_opt(2024, 3, 2).unwrap());
+        let days = vec![
+            (day(2024, 1, 20), None, dec!(100), dec!(-30)),
+            (day(2024, 1, 31), None, dec!(0), dec!(-20.5)),
+            (day(2024, 3, 1), None, dec!(10), dec!(0)),
+        ];
+        let starts = periods(Period::Month, (from, to)).unwrap();
+        let items = bucket(Period::Month, &starts, days).unwrap();
+        let summary: Vec<_> = items
+            .iter()
+            .map(|item| (item.period.as_str(), item.income, item.expense, item.net))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("2024-01", dec!(100), dec!(-50.5), dec!(49.5)),
+                ("2024-02", dec!(0), dec!(0), dec!(0)),
+                ("2024-03", dec!(10), dec!(0), dec!(10)),
+            ]
+        );
+        assert_eq!(items[1].timestamp, 1706716800);
+    }
+
+    #[test]
+    fn test_bucket_split_by_key() {
+        let from = timestamp(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        let to = timestamp(NaiveDate::from_ymd_opt(2024, 12, 31).unwrap());
+        let days = vec![
+            (
+                day(2024, 2, 1),
+                Some("餐饮".to_string()),
+                dec!(0),
+                dec!(-30),
+            ),
+            (day(2024, 5, 1), Some("交通".to_string()), dec!(0), dec!(-5)),
+        ];
+        let starts = periods(Period::Year, (from, to)).unwrap();
+        let items = bucket(Period::Year, &starts, days).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].key.as_deref(), Some("交通"));
+        assert_eq!(items[0].expense, dec!(-5));
+        assert_eq!(items[1].key.as_deref(), Some("餐饮"));
+        assert_eq!(items[1].period, "2024");
+    }
+
+    #[test]
+    fn test_bucket_sums_small_amounts_exactly() {
+        let from = timestamp(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        let to = timestamp(NaiveDate::from_ymd_opt(2024, 12, 31).unwrap());
+        // 每天一笔 0.1 元收入、0.07 元支出
+        let days = (day(2024, 1, 1)..=day(2024, 12, 31))
+            .map(|day| (day, None, dec!(0.1), dec!(-0.07)))
+            .collect();
+        let starts = periods(Period::Year, (from, to)).unwrap();
+        let items = bucket(Period::Year, &starts, days).unwrap();
+        assert_eq!(items[0].income, dec!(36.6));
+        assert_eq!(items[0].expense, dec!(-25.62));
+        assert_eq!(items[0].net, dec!(10.98));
+    }
+
+    #[test]
+    fn test_range_is_validated() {
+        let from = timestamp(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        let to = timestamp(NaiveDate::from_ymd_opt(2024, 12, 31).unwrap());
+        assert_eq!(periods(Period::Month, (from, to)).unwrap().len(), 12);
+        assert!(periods(Period::Month, (to, from)).is_err());
+        assert!(periods(Period::Day, (i64::MIN, i64::MAX)).is_err());
+        assert!(periods(Period::Day, (0, i64::MAX / 2)).is_err());
+        assert!(periods(Period::Year, (0, 4_000_000_000)).is_ok());
+        let starts = periods(Period::Day, (from, to)).unwrap();
+        let days = (0..30)
+            .map(|i| (day(2024, 1, 1), Some(i.to_string()), dec!(1), dec!(0)))
+            .collect();
+        assert!(bucket(Period::Day, &starts, days).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+use log::warn;
+use regex::Regex;
+use rust_decimal::Decimal;
+use sqlx::Pool;
+
+use super::{split, split_list, tag, update_fund, Db, FundInfo};
+use crate::{
+    api::error::StatusError,
+    storage::{InsertId, Money, NullableMoney},
+};
+
+/// 自动分类规则，条件都满足时命中，按 `priority` 从小到大取第一条命中的规则
+///
+/// 命中后 `class`、`source` 覆盖原值，`tags` 追加到原有标签
+#[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Rule {
+    id: Option<u32>,
+    #[serde(default)]
+    priority: i32,
+    /// 名称包含的文字，`regex` 为真时为正则表达式
+    pattern: Option<String>,
+    #[serde(default)]
+    regex: bool,
+    #[sqlx(try_from = "NullableMoney")]
+    min_amount: Option<Decimal>,
+    #[sqlx(try_from = "NullableMoney")]
+    max_amount: Option<Decimal>,
+    /// 匹配的资金来源
+    source: Option<String>,
+    set_class: Option<String>,
+    set_source: Option<String>,
+    /// 逗号分隔的标签
+    set_tags: Option<String>,
+}
+
+/// 编译好正则的规则
+struct Matcher {
+    rule: Rule,
+    regex: Option<Regex>,
+}
+
+impl Matcher {
+    fn new(rule: Rule) -> anyhow::Result<Self> {
+        let regex = match (&rule.pattern, rule.regex) {
+            (Some(pattern), true) => Some(Regex::new(pattern).map_err(|err| {
+                StatusError::bad_request(format!("invalid pattern {pattern}: {err}"))
+            })?),
+            _ => None,
+        };
+        Ok(Matcher { rule, regex })
+    }
+
+    fn matches(&self, fund: &FundInfo) -> bool {
+        let rule = &self.rule;
+        let name = match (&self.regex, &rule.pattern) {
+            (Some(regex), _) => regex.is_match(&fund.name),
+            (None, Some(pattern)) => fund.name.contains(pattern.as_str()),
+            (None, None) => true,
+        };
+        name && rule.min_amount.is_none_or(|min| fund.amount >= min)
+            && rule.max_amount.is_none_or(|max| fund.amount <= max)
+            && rule
+                .source
+                .as_ref()
+                .is_none_or(|source| *source == fund.source)
+    }
+
+    /// 修改资金记录，返回是否有变化
+    fn apply(&self, fund: &mut FundInfo) -> bool {
+        let rule = &self.rule;
+        let mut changed = false;
+        if let Some(class) = rule
+            .set_class
+            .as_ref()
+            .filter(|class| **class != fund.class)
+        {
+            fund.class = class.clone();
+            changed = true;
+        }
+        if let Some(source) = rule
+            .set_source
+            .as_ref()
+            .filter(|source| **source != fund.source)
+        {
+            fund.source = source.clone();
+            changed = true;
+        }
+        for tag in split_list(rule.set_tags.clone()) {
+            if !fund.tags.contains(&tag) {
+                fund.tags.push(tag);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// 按优先级排列的全部规则
+pub struct Rules(Vec<Matcher>);
+
+impl Rules {
+    pub async fn load(pool: &Pool<Db>) -> anyhow::Result<Self> {
+        let rules = get_rules(pool).await?;
+        Ok(Rules(
+            rules
+                .into_iter()
+                .map(Matcher::new)
+                .collect::<anyhow::Result<_>>()?,
+        ))
+    }
+
+    /// 用第一条命中的规则修改资金记录，返回是否有变化
+    pub fn apply(&self, fund: &mut FundInfo) -> bool {
+        self.0
+            .iter()
+            .find(|matcher| matcher.matches(fund))
+            .is_some_and(|matcher| matcher.apply(fund))
+    }
+}
+
+pub async fn get_rules(pool: &Pool<Db>) -> anyhow::Result<Vec<Rule>> {
+    let sql = "SELECT * FROM pixiu_rule ORDER BY priority, id";
+    let rows = sqlx::query_as(sql).fetch_all(pool).await?;
+    Ok(rows)
+}
+
+/// 校验正则和标签，空字符串视为不限
+fn validate(mut rule: Rule) -> anyhow::Result<Rule> {
+    let empty = |value: Option<String>| value.filter(|value| !value.is_empty());
+    rule.pattern = empty(rule.pattern);
+    rule.source = empty(rule.source);
+    rule.set_class = empty(rule.set_class);
+    rule.set_source = empty(rule.set_source);
+    let tags = tag::normalize_all(&split_list(rule.set_tags))?;
+    rule.set_tags = Some(tags.join(",")).filter(|tags| !tags.is_empty());
+    Ok(Matcher::new(rule)?.rule)
+}
+
+pub async fn insert_rule(pool: &Pool<Db>, info: Rule) -> anyhow::Result<u64> {
+    let info = validate(info)?;
+    let sql = "INSERT INTO pixiu_rule (priority, pattern, regex, min_amount, max_amount, source,
+        set_class, set_source, set_tags) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)";
+    let result = sqlx::query(sql)
+        .bind(info.priority)
+        .bind(info.pattern)
+        .bind(info.regex)
+        .bind(info.min_amount.map(Money))
+        .bind(info.max_amount.map(Money))
+        .bind(info.source)
+        .bind(info.set_class)
+        .bind(info.set_source)
+        .bind(info.set_tags)
+        .execute(pool)
+        .await?;
+    Ok(result.insert_id())
+}
+
+pub async fn update_rule(pool: &Pool<Db>, id: u32, info: Rule) -> anyhow::Result<()> {
+    let info = validate(info)?;
+    let sql = "UPDATE pixiu_rule SET priority = ?, pattern = ?, regex = ?, min_amount = ?,
+        max_amount = ?, source = ?, set_class = ?, set_source = ?, set_tags = ? WHERE id = ?";
+    sqlx::query(sql)
+        .bind(info.priority)
+        .bind(info.pattern)
+        .bind(info.regex)
+        .bind(info.min_amount.map(Money))
+        .bind(info.max_amount.map(Money))
+        .bind(info.source)
+        .bind(info.set_class)
+        .bind(info.set_source)
+        .bind(info.set_tags)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_rule(pool: &Pool<Db>, id: u32) -> anyhow::Result<()> {
+    let sql = "DELETE FROM pixiu_rule WHERE id = ?";
+    sqlx::query(sql).bind(id).execute(pool).await?;
+    Ok(())
+}
+
+/// 时间范围内的资金记录及其标签
+async fn get_history(pool: &Pool<Db>, from: i64, to: i64) -> anyhow::Result<Vec<FundInfo>> {
+    let sql = "SELECT * FROM pixiu_fund_info WHERE timestamp BETWEEN ? AND ?
+        AND deleted_at IS NULL ORDER BY timestamp DESC, id";
+    let mut funds = sqlx::query_as(sql)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+    tag::fill_tags(pool, &mut funds).await?;
+    Ok(funds)
+}
+
+/// 用历史记录试运行一条规则，返回命中的记录（应用规则后的结果），不写入数据库
+pub async fn test_rule(
+    pool: &Pool<Db>,
+    info: Rule,
+    from: i64,
+    to: i64,
+) -> anyhow::Result<Vec<FundInfo>> {
+    let matcher = Matcher::new(validate(info)?)?;
+    let mut funds = get_history(pool, from, to).await?;
+    funds.retain(|fund| matcher.matches(fund));
+    for fund in funds.iter_mut() {
+        matcher.apply(fund);
+    }
+    Ok(funds)
+}
+
+/// 对时间范围内未对账的记录重新应用全部规则，返回修改的条数
+///
+/// 改到的来源账户在该时间已对账时跳过这条记录，换了来源账户的记录改用新账户的币种
+pub async fn apply_rules(pool: &Pool<Db>, from: i64, to: i64, client: &str) -> anyhow::Result<u64> {
+    let rules = Rules::load(pool).await?;
+    let mut funds = get_history(pool, from, to).await?;
+    split::fill_splits(pool, &mut funds).await?;
+    funds.retain_mut(|fund| {
+        let source = fund.source.clone();
+        let changed = !fund.reconciled && rules.apply(fund);
+        if fund.source != source {
+            fund.currency.clear();
+        }
+        changed
+    });
+    let mut tx = pool.begin().await?;
+    let mut count = 0;
+    for fund in &funds {
+        let Some(id) = fund.id else {
+            continue;
+        };
+        if let Err(err) = update_fund(&mut tx, id, fund, client).await {
+            if err.downcast_ref::<StatusError>().is_none() {
+                return Err(err);
+            }
+            warn!("rules not applied to fund {id}: {err:#}");
+            continue;
+        }
+        count += 1;
+    }
+    tx.commit().await?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn rule(pattern: &str, regex: bool, set_class: &str) -> Rule {
+        Rule {
+            id: None,
+            priority: 0,
+            pattern: Some(pattern.to_string()),
+            regex,
+            min_amount: None,
+            max_amount: None,
+            source: None,
+            set_class: Some(set_class.to_string()),
+            set_source: None,
+            set_tags: None,
+        }
+    }
+
+    fn fund(name: &str, amount: Decimal) -> FundInfo {
+        FundInfo::sample(amount, name)
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let mut large = rule("美团", false, "大额外卖");
+        large.min_amount = Some(dec!(-1000));
+        large.max_amount = Some(dec!(-100));
+        let mut takeout = rule("^(美团|饿了么)", true, "外卖");
+        takeout.set_tags = Some("takeout".to_string());
+        let rules = Rules(vec![
+            Matcher::new(large).unwrap(),
+            Matcher::new(takeout).unwrap(),
+        ]);
+
+        let mut fund1 = fund("美团 午饭", dec!(-35));
+        assert!(rules.apply(&mut fund1));
+        assert_eq!(fund1.class, "外卖");
+        assert_eq!(fund1.tags, vec!["takeout"]);
+        // 再次应用没有变化
+        assert!(!rules.apply(&mut fund1));
+
+        let mut fund2 = fund("美团 聚餐", dec!(-300));
+        assert!(rules.apply(&mut fund2));
+        assert_eq!(fund2.class, "大额外卖");
+
+        let mut fund3 = fund("星巴克 美团", dec!(-30));
+        assert!(!rules.apply(&mut fund3));
+        assert_eq!(fund3.class, "餐饮美食");
+    }
+
+    #[test]
+    fn test_rule_matches_source() {
+        let mut wechat = rule("红包", false, "人情");
+        wechat.source = Some("微信".to_string());
+        let matcher = Matcher::new(wechat).unwrap();
+        assert!(!matcher.matches(&fund("红包", dec!(8.88))));
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_regex() {
+        assert!(validate(rule("(", true, "x")).is_err());
+        // 非正则时按字面量匹配
+        assert!(validate(rule("(", false, "x")).is_ok());
+    }
+}
@@ -0,0 +1,148 @@
+use rust_decimal::Decimal;
+use sqlx::{Pool, QueryBuilder, Transaction};
+
+use super::{Db, FundInfo};
+use crate::{api::error::StatusError, storage::Money};
+
+/// 按拆分展开的资金记录，未拆分的记录为一行，拆分的记录每个拆分一行
+///
+/// 列名与 `pixiu_fund_info` 相同，分类统计和收支合计从这里查询
+pub(super) const FUND_LINES_SQL: &str = "(SELECT pfi.id, pfi.name, pfi.timestamp, pfi.source,
+        pfi.currency, pfi.deleted_at,
+        COALESCE(pfs.class, pfi.class) AS class, COALESCE(pfs.amount, pfi.amount) AS amount
+    FROM pixiu_fund_info pfi
+    LEFT JOIN pixiu_fund_split pfs ON pfs.fund_id = pfi.id) pixiu_fund_line";
+
+/// 一笔资金记录按分类拆分的部分，各部分之和等于记录金额
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct FundSplit {
+    pub(super) class: String,
+    #[sqlx(try_from = "Money")]
+    pub(super) amount: Decimal,
+}
+
+impl FundInfo {
+    /// 按分类展开的金额，与 `FUND_LINES_SQL` 的行对应
+    pub(super) fn lines(&self) -> Vec<(&str, Decimal)> {
+        if self.splits.is_empty() {
+            return vec![(self.class.as_str(), self.amount)];
+        }
+        self.splits
+            .iter()
+            .map(|split| (split.class.as_str(), split.amount))
+            .collect()
+    }
+}
+
+/// 拆分的分类不能为空，金额之和必须等于记录金额
+fn validate(amount: Decimal, splits: &[FundSplit]) -> anyhow::Result<()> {
+    if splits.is_empty() {
+        return Ok(());
+    }
+    if splits.iter().any(|split| split.class.trim().is_empty()) {
+        return Err(StatusError::bad_request("split class is empty").into());
+    }
+    let total: Decimal = splits.iter().map(|split| split.amount).sum();
+    if total != amount {
+        let message = format!("splits sum to {total}, expected {amount}");
+        return Err(StatusError::bad_request(message).into());
+    }
+    Ok(())
+}
+
+/// 替换资金记录的拆分，为空时取消拆分
+pub(super) async fn set_fund_splits(
+    tx: &mut Transaction<'_, Db>,
+    fund_id: u32,
+    fund: &FundInfo,
+) -> anyhow::Result<()> {
+    validate(fund.amount, &fund.splits)?;
+    let sql = "DELETE FROM pixiu_fund_split WHERE fund_id = ?";
+    sqlx::query(sql).bind(fund_id).execute(&mut **tx).await?;
+    if fund.splits.is_empty() {
+        return Ok(());
+    }
+    let mut qb = QueryBuilder::new("INSERT INTO pixiu_fund_split (fund_id, class, amount) ");
+    qb.push_values(&fund.splits, |mut b, split| {
+        b.push_bind(fund_id)
+            .push_bind(split.class.trim().to_string())
+            .push_bind(Money(split.amount));
+    });
+    qb.build().execute(&mut **tx).await?;
+    Ok(())
+}
+
+/// 事务中读取一条资金记录的拆分
+pub(super) async fn get_fund_splits(
+    tx: &mut Transaction<'_, Db>,
+    fund_id: u32,
+) -> anyhow::Result<Vec<FundSplit>> {
+    let sql = "SELECT class, amount FROM pixiu_fund_split WHERE fund_id = ? ORDER BY id";
+    let rows = sqlx::query_as(sql)
+        .bind(fund_id)
+        .fetch_all(&mut **tx)
+        .await?;
+    Ok(rows)
+}
+
+/// 查询一批资金记录的拆分
+pub(super) async fn fill_splits(pool: &Pool<Db>, funds: &mut [FundInfo]) -> anyhow::Result<()> {
+    let ids: Vec<u32> = funds.iter().filter_map(|fund| fund.id).collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let mut qb =
+        QueryBuilder::new("SELECT fund_id, class, amount FROM pixiu_fund_split WHERE fund_id IN (");
+    let mut separated = qb.separated(", ");
+    for id in ids {
+        separated.push_bind(id);
+    }
+    separated.push_unseparated(") ORDER BY id");
+    let rows: Vec<(u32, String, Money)> = qb.build_query_as().fetch_all(pool).await?;
+    for fund in funds.iter_mut() {
+        fund.splits = rows
+            .iter()
+            .filter(|(fund_id, _, _)| Some(*fund_id) == fund.id)
+            .map(|(_, class, amount)| FundSplit {
+                class: class.clone(),
+                amount: amount.0,
+            })
+            .collect();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn split(class: &str, amount: Decimal) -> FundSplit {
+        FundSplit {
+            class: class.to_string(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn test_splits_must_sum_to_amount() {
+        let splits = vec![
+            split("食品", dec!(-52.30)),
+            split("日用", dec!(-30.00)),
+            split("零食", dec!(-17.70)),
+        ];
+        assert!(validate(dec!(-100), &splits).is_ok());
+        assert!(validate(dec!(-100.01), &splits).is_err());
+        assert!(validate(dec!(-100), &[]).is_ok());
+        assert!(validate(dec!(-1), &[split(" ", dec!(-1))]).is_err());
+    }
+
+    #[test]
+    fn test_lines_follow_splits() {
+        let mut fund = FundInfo::sample(dec!(-100), "超市");
+        assert_eq!(fund.lines(), vec![("餐饮美食", dec!(-100))]);
+        fund.splits = vec![split("食品", dec!(-70)), split("日用", dec!(-30))];
+        assert_eq!(fund.lines(), vec![("食品", dec!(-70)), ("日用", dec!(-30))]);
+    }
+}
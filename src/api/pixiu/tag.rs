@@ -0,0 +1,201 @@
+use sqlx::{Pool, QueryBuilder, Transaction};
+
+use super::{
+    currency, split::FUND_LINES_SQL, sum_by_name, Db, FundFilter, FundInfo, SumInfo, DIALECT,
+};
+use crate::{
+    api::error::StatusError,
+    storage::{InsertId, Money},
+};
+
+/// 标签及其关联的资金记录数
+#[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
+pub struct TagInfo {
+    id: Option<u32>,
+    name: String,
+    #[serde(default)]
+    count: i64,
+}
+
+/// 去掉首尾空白，标签不能为空，也不能含逗号（筛选时以逗号分隔）
+fn normalize(name: &str) -> anyhow::Result<String> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(',') || name.chars().count() > 64 {
+        return Err(StatusError::bad_request(format!("invalid tag: {name}")).into());
+    }
+    Ok(name.to_string())
+}
+
+/// 去重并保持原有顺序
+pub(super) fn normalize_all(tags: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut names: Vec<String> = vec![];
+    for tag in tags {
+        let name = normalize(tag)?;
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+/// 重名时返回 400
+fn map_duplicate(err: sqlx::Error, name: &str) -> anyhow::Error {
+    match err.as_database_error() {
+        Some(db) if db.is_unique_violation() => {
+            StatusError::bad_request(format!("tag {name} already exists")).into()
+        }
+        _ => err.into(),
+    }
+}
+
+pub async fn get_tags(pool: &Pool<Db>) -> anyhow::Result<Vec<TagInfo>> {
+    let sql = "SELECT pt.id, pt.name, COUNT(pfi.id) AS count
+    FROM
+        pixiu_tag pt
+    LEFT JOIN
+        pixiu_fund_tag pft
+        ON pft.tag_id = pt.id
+    LEFT JOIN
+        pixiu_fund_info pfi
+        ON pfi.id = pft.fund_id AND pfi.deleted_at IS NULL
+    GROUP BY pt.id
+    ORDER BY pt.name";
+    let rows = sqlx::query_as(sql).fetch_all(pool).await?;
+    Ok(rows)
+}
+
+pub async fn insert_tag(pool: &Pool<Db>, info: TagInfo) -> anyhow::Result<u64> {
+    let name = normalize(&info.name)?;
+    let sql = "INSERT INTO pixiu_tag (name) VALUES (?)";
+    let result = sqlx::query(sql)
+        .bind(&name)
+        .execute(pool)
+        .await
+        .map_err(|err| map_duplicate(err, &name))?;
+    Ok(result.insert_id())
+}
+
+/// 重命名标签，已关联的资金记录随之改变
+pub async fn update_tag(pool: &Pool<Db>, id: u32, info: TagInfo) -> anyhow::Result<()> {
+    let name = normalize(&info.name)?;
+    let sql = "UPDATE pixiu_tag SET name = ? WHERE id = ?";
+    sqlx::query(sql)
+        .bind(&name)
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|err| map_duplicate(err, &name))?;
+    Ok(())
+}
+
+/// 删除标签，与资金记录的关联随外键级联删除
+pub async fn delete_tag(pool: &Pool<Db>, id: u32) -> anyhow::Result<()> {
+    let sql = "DELETE FROM pixiu_tag WHERE id = ?";
+    sqlx::query(sql).bind(id).execute(pool).await?;
+    Ok(())
+}
+
+/// 替换资金记录的标签，不存在的标签自动创建
+pub(super) async fn set_fund_tags(
+    tx: &mut Transaction<'_, Db>,
+    fund_id: u32,
+    tags: &[String],
+) -> anyhow::Result<()> {
+    let names = normalize_all(tags)?;
+    let sql = "DELETE FROM pixiu_fund_tag WHERE fund_id = ?";
+    sqlx::query(sql).bind(fund_id).execute(&mut **tx).await?;
+    if names.is_empty() {
+        return Ok(());
+    }
+    let mut qb = QueryBuilder::new(format!(
+        "{} INTO pixiu_tag (name) ",
+        DIALECT.insert_ignore()
+    ));
+    qb.push_values(&names, |mut b, name| {
+        b.push_bind(name.clone());
+    });
+    qb.build().execute(&mut **tx).await?;
+
+    let mut qb = QueryBuilder::new("INSERT INTO pixiu_fund_tag (fund_id, tag_id) SELECT ");
+    qb.push_bind(fund_id)
+        .push(", id FROM pixiu_tag WHERE name IN (");
+    let mut separated = qb.separated(", ");
+    for name in &names {
+        separated.push_bind(name.clone());
+    }
+    separated.push_unseparated(")");
+    qb.build().execute(&mut **tx).await?;
+    Ok(())
+}
+
+/// 查询一批资金记录的标签
+pub(super) async fn fill_tags(pool: &Pool<Db>, funds: &mut [FundInfo]) -> anyhow::Result<()> {
+    let ids: Vec<u32> = funds.iter().filter_map(|fund| fund.id).collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let mut qb = QueryBuilder::new(
+        "SELECT pft.fund_id, pt.name FROM pixiu_fund_tag pft
+        JOIN pixiu_tag pt ON pt.id = pft.tag_id WHERE pft.fund_id IN (",
+    );
+    let mut separated = qb.separated(", ");
+    for id in ids {
+        separated.push_bind(id);
+    }
+    separated.push_unseparated(") ORDER BY pt.name");
+    let rows: Vec<(u32, String)> = qb.build_query_as().fetch_all(pool).await?;
+    for fund in funds.iter_mut() {
+        fund.tags = rows
+            .iter()
+            .filter(|(fund_id, _)| Some(*fund_id) == fund.id)
+            .map(|(_, name)| name.clone())
+            .collect();
+    }
+    Ok(())
+}
+
+/// 各标签的支出，折合为 `base` 币种，带多个标签的记录计入每个标签
+pub async fn get_tag_sum_info(
+    pool: &Pool<Db>,
+    filter: &FundFilter,
+    base: &str,
+) -> anyhow::Result<Vec<SumInfo>> {
+    let mut qb = QueryBuilder::new("SELECT pt.name, pfi.currency, SUM(-pfi.amount), ");
+    currency::push_rate_time(&mut qb, base);
+    qb.push(format!(" FROM (SELECT * FROM {FUND_LINES_SQL}"));
+    filter.push_line_where(&mut qb);
+    qb.push(
+        ") pfi
+        JOIN pixiu_fund_tag pft ON pft.fund_id = pfi.id
+        JOIN pixiu_tag pt ON pt.id = pft.tag_id
+        GROUP BY pt.name, pfi.currency, rate_time",
+    );
+    let rows: Vec<(String, String, Money, i64)> = qb.build_query_as().fetch_all(pool).await?;
+    let rows = rows
+        .into_iter()
+        .map(|(name, currency, value, rate_time)| (name, currency, value.0, rate_time))
+        .collect();
+    let rates = currency::Rates::load(pool).await?;
+    sum_by_name(rows, &rates, base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_tags() {
+        let tags = vec![
+            " travel-2026-japan ".to_string(),
+            "reimbursable".to_string(),
+            "travel-2026-japan".to_string(),
+        ];
+        assert_eq!(
+            normalize_all(&tags).unwrap(),
+            vec!["travel-2026-japan", "reimbursable"]
+        );
+        assert!(normalize(" ").is_err());
+        assert!(normalize("a,b").is_err());
+        assert!(normalize(&"长".repeat(65)).is_err());
+    }
+}
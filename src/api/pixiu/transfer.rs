@@ -0,0 +1,211 @@
+use rust_decimal::Decimal;
+use sqlx::{Pool, Transaction};
+
+use super::{reconcile, Db, DIALECT};
+use crate::{
+    api::error::StatusError,
+    storage::{InsertId, Money, NullableMoney},
+};
+
+/// 账户间转账，只影响两个账户的余额，不计入收支和分类统计
+///
+/// 转出账户减少 `amount + fee`，转入账户增加 `amount`，
+/// 两个账户币种不同时转入账户增加 `to_amount`（以转入账户币种计）
+#[derive(sqlx::FromRow, Debug, serde::Deserialize, serde::Serialize)]
+pub struct TransferInfo {
+    id: Option<u32>,
+    from_source: String,
+    to_source: String,
+    #[sqlx(try_from = "Money")]
+    amount: Decimal,
+    #[serde(default)]
+    #[sqlx(try_from = "NullableMoney")]
+    to_amount: Option<Decimal>,
+    #[serde(default)]
+    #[sqlx(try_from = "Money")]
+    fee: Decimal,
+    timestamp: i64,
+    #[serde(default)]
+    remark: String,
+}
+
+impl TransferInfo {
+    /// 转出、转入账户不能相同，金额为正，手续费不为负
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.from_source == self.to_source {
+            return Err(StatusError::bad_request("from_source and to_source must differ").into());
+        }
+        if self.amount <= Decimal::ZERO {
+            return Err(StatusError::bad_request("amount must be positive").into());
+        }
+        if self
+            .to_amount
+            .is_some_and(|to_amount| to_amount <= Decimal::ZERO)
+        {
+            return Err(StatusError::bad_request("to_amount must be positive").into());
+        }
+        if self.fee < Decimal::ZERO {
+            return Err(StatusError::bad_request("fee must not be negative").into());
+        }
+        Ok(())
+    }
+
+    /// 转出、转入账户都必须是已有的资产账户
+    async fn check_sources(&self, tx: &mut Transaction<'_, Db>) -> anyhow::Result<()> {
+        let sql = "SELECT COUNT(*) FROM pixiu_property_info WHERE name = ?";
+        for source in [&self.from_source, &self.to_source] {
+            let count: i64 = sqlx::query_scalar(sql)
+                .bind(source)
+                .fetch_one(&mut **tx)
+                .await?;
+            if count == 0 {
+                return Err(StatusError::not_found(format!("property {source} not found")).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// 转账时间不能落在转出、转入账户已对账的时间段内
+    async fn check_open(&self, tx: &mut Transaction<'_, Db>) -> anyhow::Result<()> {
+        for source in [&self.from_source, &self.to_source] {
+            reconcile::check_open(tx, source, self.timestamp).await?;
+        }
+        Ok(())
+    }
+}
+
+pub async fn get_transfers(
+    pool: &Pool<Db>,
+    from: i64,
+    to: i64,
+) -> anyhow::Result<Vec<TransferInfo>> {
+    let sql = "SELECT * FROM pixiu_transfer WHERE timestamp BETWEEN ? AND ?
+        ORDER BY timestamp DESC, id";
+    let rows = sqlx::query_as(sql)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows)
+}
+
+/// 事务中读取并锁定转账，不存在时返回 404
+async fn get_transfer_for_update(
+    tx: &mut Transaction<'_, Db>,
+    id: u32,
+) -> anyhow::Result<TransferInfo> {
+    let sql = format!(
+        "SELECT * FROM pixiu_transfer WHERE id = ?{}",
+        DIALECT.for_update()
+    );
+    let transfer: Option<TransferInfo> = sqlx::query_as(&sql)
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    transfer.ok_or_else(|| StatusError::not_found(format!("transfer {id} not found")).into())
+}
+
+pub async fn insert_transfer(pool: &Pool<Db>, info: TransferInfo) -> anyhow::Result<u64> {
+    info.validate()?;
+    let mut tx = pool.begin().await?;
+    info.check_sources(&mut tx).await?;
+    info.check_open(&mut tx).await?;
+    let sql = "INSERT INTO pixiu_transfer
+        (from_source, to_source, amount, to_amount, fee, timestamp, remark)
+        VALUES (?, ?, ?, ?, ?, ?, ?)";
+    let result = sqlx::query(sql)
+        .bind(info.from_source)
+        .bind(info.to_source)
+        .bind(Money(info.amount))
+        .bind(info.to_amount.map(Money))
+        .bind(Money(info.fee))
+        .bind(info.timestamp)
+        .bind(info.remark)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(result.insert_id())
+}
+
+/// 改动前后的转账都不能落在已对账的时间段内
+pub async fn update_transfer(pool: &Pool<Db>, id: u32, info: TransferInfo) -> anyhow::Result<()> {
+    info.validate()?;
+    let mut tx = pool.begin().await?;
+    get_transfer_for_update(&mut tx, id)
+        .await?
+        .check_open(&mut tx)
+        .await?;
+    info.check_sources(&mut tx).await?;
+    info.check_open(&mut tx).await?;
+    let sql = "UPDATE pixiu_transfer SET from_source = ?, to_source = ?, amount = ?,
+        to_amount = ?, fee = ?, timestamp = ?, remark = ? WHERE id = ?";
+    sqlx::query(sql)
+        .bind(info.from_source)
+        .bind(info.to_source)
+        .bind(Money(info.amount))
+        .bind(info.to_amount.map(Money))
+        .bind(Money(info.fee))
+        .bind(info.timestamp)
+        .bind(info.remark)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn delete_transfer(pool: &Pool<Db>, id: u32) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    get_transfer_for_update(&mut tx, id)
+        .await?
+        .check_open(&mut tx)
+        .await?;
+    let sql = "DELETE FROM pixiu_transfer WHERE id = ?";
+    sqlx::query(sql).bind(id).execute(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn transfer(from_source: &str, to_source: &str, amount: Decimal, fee: Decimal) -> TransferInfo {
+        TransferInfo {
+            id: None,
+            from_source: from_source.to_string(),
+            to_source: to_source.to_string(),
+            amount,
+            to_amount: None,
+            fee,
+            timestamp: 1704040200,
+            remark: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_transfer_is_validated() {
+        assert!(transfer("招商银行", "支付宝", dec!(100), dec!(0))
+            .validate()
+            .is_ok());
+        assert!(transfer("招商银行", "支付宝", dec!(100), dec!(1.5))
+            .validate()
+            .is_ok());
+        for info in [
+            transfer("招商银行", "招商银行", dec!(100), dec!(0)),
+            transfer("招商银行", "支付宝", dec!(0), dec!(0)),
+            transfer("招商银行", "支付宝", dec!(-100), dec!(0)),
+            transfer("招商银行", "支付宝", dec!(100), dec!(-1)),
+            TransferInfo {
+                to_amount: Some(dec!(0)),
+                ..transfer("招商银行", "支付宝", dec!(100), dec!(0))
+            },
+        ] {
+            let err = info.validate().unwrap_err();
+            let StatusError(status, _) = err.downcast_ref::<StatusError>().unwrap();
+            assert_eq!(*status, axum::http::StatusCode::BAD_REQUEST);
+        }
+    }
+}
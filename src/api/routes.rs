@@ -0,0 +1,960 @@
+use axum::{
+    body::Bytes,
+    extract::{multipart::MultipartError, DefaultBodyLimit, Json, Multipart, Path, Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Router,
+};
+use sqlx::Pool;
+
+use super::{Db, DIALECT};
+use crate::api::error::{AppError, StatusError};
+
+pub mod pixiu;
+
+pub fn router() -> Router<Pool<Db>> {
+    Router::new()
+        .route("/pixiu/fund", post(pixiu_insert_fund_info))
+        .route("/pixiu/fund", get(pixiu_get_fund_info))
+        .route("/pixiu/fund/{id}", delete(pixiu_delete_fund_info))
+        .route("/pixiu/fund/{id}", put(pixiu_update_fund_info))
+        .route("/pixiu/fund/batch", post(pixiu_batch_fund_info))
+        .route("/pixiu/fund/{id}/restore", post(pixiu_restore_fund_info))
+        .route("/pixiu/fund/audit", get(pixiu_get_fund_audits))
+        .route(
+            "/pixiu/fund/audit/{id}/revert",
+            post(pixiu_revert_fund_audit),
+        )
+        .route("/pixiu/fund/export", get(pixiu_export_fund_info))
+        .route("/pixiu/fund/{id}/attachment", get(pixiu_get_attachments))
+        .route(
+            "/pixiu/fund/{id}/attachment",
+            post(pixiu_upload_attachments)
+                .layer(DefaultBodyLimit::max(pixiu::attachment::MAX_SIZE * 5)),
+        )
+        .route("/pixiu/attachment/{id}", get(pixiu_download_attachment))
+        .route("/pixiu/attachment/{id}", delete(pixiu_delete_attachment))
+        .route("/pixiu/fund/report", get(pixiu_get_fund_report))
+        .route("/pixiu/fund/import", post(pixiu_import_fund_info))
+        .route(
+            "/pixiu/fund/import/preview",
+            post(pixiu_preview_fund_import),
+        )
+        .route("/pixiu/fund/sources", get(pixiu_get_fund_sources))
+        .route("/pixiu/fund/types", get(pixiu_get_fund_types))
+        .route("/pixiu/category", get(pixiu_get_categories))
+        .route("/pixiu/category", post(pixiu_insert_category))
+        .route("/pixiu/category/{id}", put(pixiu_update_category))
+        .route("/pixiu/category/{id}", delete(pixiu_delete_category))
+        .route("/pixiu/rule", get(pixiu_get_rules))
+        .route("/pixiu/rule", post(pixiu_insert_rule))
+        .route("/pixiu/rule/{id}", put(pixiu_update_rule))
+        .route("/pixiu/rule/{id}", delete(pixiu_delete_rule))
+        .route("/pixiu/rule/test", post(pixiu_test_rule))
+        .route("/pixiu/rule/apply", post(pixiu_apply_rules))
+        .route("/pixiu/tag", get(pixiu_get_tags))
+        .route("/pixiu/tag", post(pixiu_insert_tag))
+        .route("/pixiu/tag/{id}", put(pixiu_update_tag))
+        .route("/pixiu/tag/{id}", delete(pixiu_delete_tag))
+        .route("/pixiu/tag/sum", get(pixiu_get_tag_sum_info))
+        .route("/pixiu/budget", get(pixiu_get_budget_status))
+        .route("/pixiu/budget", post(pixiu_insert_budget))
+        .route("/pixiu/budget/{id}", put(pixiu_update_budget))
+        .route("/pixiu/budget/{id}", delete(pixiu_delete_budget))
+        .route("/pixiu/recurring", get(pixiu_get_recurring))
+        .route("/pixiu/recurring", post(pixiu_insert_recurring))
+        .route("/pixiu/recurring/{id}", put(pixiu_update_recurring))
+        .route("/pixiu/recurring/{id}", delete(pixiu_delete_recurring))
+        .route(
+            "/pixiu/recurring/{id}/occurrences",
+            get(pixiu_get_recurring_occurrences),
+        )
+        .route(
+            "/pixiu/recurring/{id}/occurrences/{timestamp}",
+            put(pixiu_set_recurring_override),
+        )
+        .route(
+            "/pixiu/recurring/{id}/occurrences/{timestamp}",
+            delete(pixiu_delete_recurring_override),
+        )
+        .route("/pixiu/transfer", get(pixiu_get_transfers))
+        .route("/pixiu/transfer", post(pixiu_insert_transfer))
+        .route("/pixiu/transfer/{id}", put(pixiu_update_transfer))
+        .route("/pixiu/transfer/{id}", delete(pixiu_delete_transfer))
+        .route("/pixiu/debt", get(pixiu_get_debt_info))
+        .route("/pixiu/debt", post(pixiu_insert_debt_info))
+        .route("/pixiu/debt/{id}", put(pixiu_update_debt_info))
+        .route("/pixiu/debt/{id}", delete(pixiu_delete_debt_info))
+        .route("/pixiu/debt/{id}/schedule", get(pixiu_get_debt_schedule))
+        .route("/pixiu/debt/{id}/repayment", get(pixiu_get_debt_repayments))
+        .route(
+            "/pixiu/debt/{id}/repayment",
+            post(pixiu_insert_debt_repayment),
+        )
+        .route(
+            "/pixiu/debt/{id}/repayment/{repayment_id}",
+            delete(pixiu_delete_debt_repayment),
+        )
+        .route("/pixiu/property", get(pixiu_get_property_info))
+        .route("/pixiu/property", post(pixiu_insert_property_info))
+        .route("/pixiu/property/{id}", put(pixiu_update_property_info))
+        .route(
+            "/pixiu/property/{id}/archive",
+            post(pixiu_archive_property_info),
+        )
+        .route(
+            "/pixiu/property/{id}/archive",
+            delete(pixiu_unarchive_property_info),
+        )
+        .route("/pixiu/property/assertion", get(pixiu_get_assertions))
+        .route("/pixiu/property/assertion", post(pixiu_insert_assertion))
+        .route(
+            "/pixiu/property/assertion/{id}",
+            delete(pixiu_delete_assertion),
+        )
+        .route("/pixiu/exchange-rate", get(pixiu_get_exchange_rates))
+        .route("/pixiu/exchange-rate", post(pixiu_insert_exchange_rate))
+        .route(
+            "/pixiu/exchange-rate/{id}",
+            delete(pixiu_delete_exchange_rate),
+        )
+        .route(
+            "/pixiu/exchange-rate/import",
+            post(pixiu_import_exchange_rates),
+        )
+        .route("/pixiu/net-worth", get(pixiu_get_net_worth))
+        .route("/pixiu/net-worth", post(pixiu_snapshot_net_worth))
+        .route("/pixiu/net-worth/chart", get(pixiu_get_net_worth_chart))
+}
+
+/// 审计日志中的客户端，取请求的 User-Agent
+fn client(headers: &HeaderMap) -> String {
+    headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+async fn pixiu_insert_fund_info(
+    State(pool): State<Pool<Db>>,
+    headers: HeaderMap,
+    Json(payload): Json<pixiu::FundInfo>,
+) -> Result<(), AppError> {
+    pixiu::insert_fund_info(&pool, payload, &client(&headers)).await?;
+    Ok(())
+}
+
+async fn pixiu_export_fund_info(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<ExportRequest>,
+) -> Result<Response, AppError> {
+    let filter = pixiu::FundFilter::new(
+        params.from,
+        params.to,
+        params.source,
+        params.fund_type,
+        params.name,
+        params.tags,
+    );
+    let export = pixiu::export::export(pool, filter, params.format).await?;
+    let disposition = format!("attachment; filename=\"{}\"", export.file_name);
+    Ok((
+        [
+            (header::CONTENT_TYPE, export.content_type.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        export.body,
+    )
+        .into_response())
+}
+
+async fn pixiu_get_attachments(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<Json<Vec<pixiu::attachment::Attachment>>, AppError> {
+    let attachments = pixiu::attachment::get_attachments(&pool, id).await?;
+    Ok(Json(attachments))
+}
+
+/// 上传附件，表单中每个文件字段为一个附件
+async fn pixiu_upload_attachments(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    mut multipart: Multipart,
+) -> Result<Json<Vec<u64>>, AppError> {
+    // 表单格式错误时返回 400 而不是 500
+    let invalid = |err: MultipartError| StatusError(err.status(), err.body_text());
+    let mut uploads = vec![];
+    while let Some(field) = multipart.next_field().await.map_err(invalid)? {
+        let Some(file_name) = field.file_name().map(str::to_string) else {
+            continue;
+        };
+        let bytes = field.bytes().await.map_err(invalid)?;
+        uploads.push(pixiu::attachment::Upload {
+            file_name,
+            bytes: bytes.to_vec(),
+        });
+    }
+    let ids = pixiu::attachment::insert_attachments(&pool, id, uploads).await?;
+    Ok(Json(ids))
+}
+
+async fn pixiu_download_attachment(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<Response, AppError> {
+    let (attachment, bytes) = pixiu::attachment::read_attachment(&pool, id).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, attachment.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                attachment.content_disposition(),
+            ),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        bytes,
+    )
+        .into_response())
+}
+
+async fn pixiu_delete_attachment(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<(), AppError> {
+    pixiu::attachment::delete_attachment(&pool, id).await?;
+    Ok(())
+}
+
+async fn pixiu_get_fund_report(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<ReportRequest>,
+) -> Result<Json<Vec<pixiu::report::ReportItem>>, AppError> {
+    let filter = pixiu::FundFilter::new(
+        params.from,
+        params.to,
+        params.source,
+        params.fund_type,
+        params.name,
+        params.tags,
+    );
+    let base = pixiu::currency::base(params.currency.as_deref())?;
+    let report =
+        pixiu::report::get_report(&pool, &filter, params.period, params.split, &base).await?;
+    Ok(Json(report))
+}
+
+async fn pixiu_preview_fund_import(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<ImportRequest>,
+    body: Bytes,
+) -> Result<Json<pixiu::import::ImportPreview>, AppError> {
+    let preview = pixiu::import::preview(&pool, params.platform, params.source, &body).await?;
+    Ok(Json(preview))
+}
+
+async fn pixiu_import_fund_info(
+    State(pool): State<Pool<Db>>,
+    headers: HeaderMap,
+    Json(payload): Json<Vec<pixiu::FundInfo>>,
+) -> Result<Json<u64>, AppError> {
+    let count = pixiu::import::commit(&pool, payload, &client(&headers)).await?;
+    Ok(Json(count))
+}
+
+async fn pixiu_get_fund_info(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<PageRequest>,
+) -> Result<Json<PageResponse<pixiu::FundInfo>>, AppError> {
+    let page = params.page()?;
+    let filter = params.filter();
+    let base = pixiu::currency::base(params.currency.as_deref())?;
+    // 三个查询互不依赖，并发执行
+    let (total, funds, summary) = tokio::try_join!(
+        pixiu::count(&pool, &filter),
+        pixiu::get_fund_info(&pool, &filter, &page),
+        pixiu::get_summary(&pool, &filter, params.level, &base),
+    )?;
+    let next_cursor = page.next_cursor(&funds);
+    let response = PageResponse {
+        total,
+        data: funds,
+        next_cursor,
+        summary,
+    };
+    Ok(Json(response))
+}
+
+async fn pixiu_get_categories(
+    State(pool): State<Pool<Db>>,
+) -> Result<Json<Vec<pixiu::category::Category>>, AppError> {
+    let categories = pixiu::category::get_categories(&pool).await?;
+    Ok(Json(categories))
+}
+
+async fn pixiu_insert_category(
+    State(pool): State<Pool<Db>>,
+    Json(payload): Json<pixiu::category::Category>,
+) -> Result<Json<u64>, AppError> {
+    let id = pixiu::category::insert_category(&pool, payload).await?;
+    Ok(Json(id))
+}
+
+async fn pixiu_update_category(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+    Json(payload): Json<pixiu::category::Category>,
+) -> Result<(), AppError> {
+    pixiu::category::update_category(&pool, id, payload, &client(&headers)).await?;
+    Ok(())
+}
+
+async fn pixiu_delete_category(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<(), AppError> {
+    pixiu::category::delete_category(&pool, id).await?;
+    Ok(())
+}
+
+async fn pixiu_get_rules(
+    State(pool): State<Pool<Db>>,
+) -> Result<Json<Vec<pixiu::rule::Rule>>, AppError> {
+    let rules = pixiu::rule::get_rules(&pool).await?;
+    Ok(Json(rules))
+}
+
+async fn pixiu_insert_rule(
+    State(pool): State<Pool<Db>>,
+    Json(payload): Json<pixiu::rule::Rule>,
+) -> Result<Json<u64>, AppError> {
+    let id = pixiu::rule::insert_rule(&pool, payload).await?;
+    Ok(Json(id))
+}
+
+async fn pixiu_update_rule(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    Json(payload): Json<pixiu::rule::Rule>,
+) -> Result<(), AppError> {
+    pixiu::rule::update_rule(&pool, id, payload).await?;
+    Ok(())
+}
+
+async fn pixiu_delete_rule(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<(), AppError> {
+    pixiu::rule::delete_rule(&pool, id).await?;
+    Ok(())
+}
+
+/// 用时间范围内的历史记录试运行规则
+async fn pixiu_test_rule(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<RangeRequest>,
+    Json(payload): Json<pixiu::rule::Rule>,
+) -> Result<Json<Vec<pixiu::FundInfo>>, AppError> {
+    let funds = pixiu::rule::test_rule(&pool, payload, params.from, params.to).await?;
+    Ok(Json(funds))
+}
+
+/// 对时间范围内的已有记录重新应用全部规则
+async fn pixiu_apply_rules(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<RangeRequest>,
+    headers: HeaderMap,
+) -> Result<Json<u64>, AppError> {
+    let count = pixiu::rule::apply_rules(&pool, params.from, params.to, &client(&headers)).await?;
+    Ok(Json(count))
+}
+
+async fn pixiu_get_tags(
+    State(pool): State<Pool<Db>>,
+) -> Result<Json<Vec<pixiu::tag::TagInfo>>, AppError> {
+    let tags = pixiu::tag::get_tags(&pool).await?;
+    Ok(Json(tags))
+}
+
+async fn pixiu_insert_tag(
+    State(pool): State<Pool<Db>>,
+    Json(payload): Json<pixiu::tag::TagInfo>,
+) -> Result<Json<u64>, AppError> {
+    let id = pixiu::tag::insert_tag(&pool, payload).await?;
+    Ok(Json(id))
+}
+
+async fn pixiu_update_tag(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    Json(payload): Json<pixiu::tag::TagInfo>,
+) -> Result<(), AppError> {
+    pixiu::tag::update_tag(&pool, id, payload).await?;
+    Ok(())
+}
+
+async fn pixiu_delete_tag(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<(), AppError> {
+    pixiu::tag::delete_tag(&pool, id).await?;
+    Ok(())
+}
+
+async fn pixiu_get_tag_sum_info(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<TagSumRequest>,
+) -> Result<Json<Vec<pixiu::SumInfo>>, AppError> {
+    let filter = pixiu::FundFilter::new(
+        params.from,
+        params.to,
+        params.source,
+        params.fund_type,
+        params.name,
+        params.tags,
+    );
+    let base = pixiu::currency::base(params.currency.as_deref())?;
+    let sums = pixiu::tag::get_tag_sum_info(&pool, &filter, &base).await?;
+    Ok(Json(sums))
+}
+
+async fn pixiu_get_budget_status(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<BudgetRequest>,
+) -> Result<Json<Vec<pixiu::budget::BudgetStatus>>, AppError> {
+    let timestamp = params
+        .timestamp
+        .unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let budgets = pixiu::budget::get_budget_status(&pool, timestamp).await?;
+    Ok(Json(budgets))
+}
+
+async fn pixiu_insert_budget(
+    State(pool): State<Pool<Db>>,
+    Json(payload): Json<pixiu::budget::BudgetInfo>,
+) -> Result<Json<u64>, AppError> {
+    let id = pixiu::budget::insert_budget(&pool, payload).await?;
+    Ok(Json(id))
+}
+
+async fn pixiu_update_budget(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    Json(payload): Json<pixiu::budget::BudgetInfo>,
+) -> Result<(), AppError> {
+    pixiu::budget::update_budget(&pool, id, payload).await?;
+    Ok(())
+}
+
+async fn pixiu_delete_budget(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<(), AppError> {
+    pixiu::budget::delete_budget(&pool, id).await?;
+    Ok(())
+}
+
+async fn pixiu_get_recurring(
+    State(pool): State<Pool<Db>>,
+) -> Result<Json<Vec<pixiu::recurring::RecurringInfo>>, AppError> {
+    let recurring = pixiu::recurring::get_recurring(&pool).await?;
+    Ok(Json(recurring))
+}
+
+async fn pixiu_insert_recurring(
+    State(pool): State<Pool<Db>>,
+    Json(payload): Json<pixiu::recurring::RecurringInfo>,
+) -> Result<Json<u64>, AppError> {
+    let id = pixiu::recurring::insert_recurring(&pool, payload).await?;
+    Ok(Json(id))
+}
+
+async fn pixiu_update_recurring(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    Json(payload): Json<pixiu::recurring::RecurringInfo>,
+) -> Result<(), AppError> {
+    pixiu::recurring::update_recurring(&pool, id, payload).await?;
+    Ok(())
+}
+
+async fn pixiu_delete_recurring(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<(), AppError> {
+    pixiu::recurring::delete_recurring(&pool, id).await?;
+    Ok(())
+}
+
+async fn pixiu_get_recurring_occurrences(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    Query(params): Query<RangeRequest>,
+) -> Result<Json<Vec<pixiu::recurring::Occurrence>>, AppError> {
+    let occurrences = pixiu::recurring::get_occurrences(&pool, id, params.from, params.to).await?;
+    Ok(Json(occurrences))
+}
+
+async fn pixiu_set_recurring_override(
+    State(pool): State<Pool<Db>>,
+    Path((id, timestamp)): Path<(u32, i64)>,
+    Json(payload): Json<pixiu::recurring::OccurrenceOverride>,
+) -> Result<(), AppError> {
+    pixiu::recurring::set_override(&pool, id, timestamp, payload).await?;
+    Ok(())
+}
+
+async fn pixiu_delete_recurring_override(
+    State(pool): State<Pool<Db>>,
+    Path((id, timestamp)): Path<(u32, i64)>,
+) -> Result<(), AppError> {
+    pixiu::recurring::delete_override(&pool, id, timestamp).await?;
+    Ok(())
+}
+
+async fn pixiu_get_transfers(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<RangeRequest>,
+) -> Result<Json<Vec<pixiu::transfer::TransferInfo>>, AppError> {
+    let transfers = pixiu::transfer::get_transfers(&pool, params.from, params.to).await?;
+    Ok(Json(transfers))
+}
+
+async fn pixiu_insert_transfer(
+    State(pool): State<Pool<Db>>,
+    Json(payload): Json<pixiu::transfer::TransferInfo>,
+) -> Result<Json<u64>, AppError> {
+    let id = pixiu::transfer::insert_transfer(&pool, payload).await?;
+    Ok(Json(id))
+}
+
+async fn pixiu_update_transfer(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    Json(payload): Json<pixiu::transfer::TransferInfo>,
+) -> Result<(), AppError> {
+    pixiu::transfer::update_transfer(&pool, id, payload).await?;
+    Ok(())
+}
+
+async fn pixiu_delete_transfer(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<(), AppError> {
+    pixiu::transfer::delete_transfer(&pool, id).await?;
+    Ok(())
+}
+
+async fn pixiu_get_debt_info(
+    State(pool): State<Pool<Db>>,
+) -> Result<Json<Vec<pixiu::DebtInfo>>, AppError> {
+    let debts = pixiu::get_debt_info(&pool).await?;
+    Ok(Json(debts))
+}
+
+async fn pixiu_insert_debt_info(
+    State(pool): State<Pool<Db>>,
+    Json(payload): Json<pixiu::DebtInfo>,
+) -> Result<Json<u64>, AppError> {
+    let id = pixiu::insert_debt_info(&pool, payload).await?;
+    Ok(Json(id))
+}
+
+async fn pixiu_update_debt_info(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    Json(payload): Json<pixiu::DebtInfo>,
+) -> Result<(), AppError> {
+    pixiu::update_debt_info(&pool, id, payload).await?;
+    Ok(())
+}
+
+async fn pixiu_delete_debt_info(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<(), AppError> {
+    pixiu::delete_debt_info(&pool, id).await?;
+    Ok(())
+}
+
+async fn pixiu_get_debt_schedule(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    Query(params): Query<ScheduleRequest>,
+) -> Result<Json<pixiu::DebtSchedule>, AppError> {
+    let prepay = params
+        .prepay_amount
+        .zip(params.prepay_timestamp)
+        .map(|(amount, timestamp)| (amount, timestamp, params.keep));
+    let schedule = pixiu::get_debt_schedule(&pool, id, prepay).await?;
+    Ok(Json(schedule))
+}
+
+async fn pixiu_get_debt_repayments(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<Json<Vec<pixiu::DebtRepayment>>, AppError> {
+    let repayments = pixiu::get_debt_repayments(&pool, id).await?;
+    Ok(Json(repayments))
+}
+
+async fn pixiu_insert_debt_repayment(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    Json(payload): Json<pixiu::DebtRepayment>,
+) -> Result<Json<u64>, AppError> {
+    let repayment_id = pixiu::insert_debt_repayment(&pool, id, payload).await?;
+    Ok(Json(repayment_id))
+}
+
+async fn pixiu_delete_debt_repayment(
+    State(pool): State<Pool<Db>>,
+    Path((id, repayment_id)): Path<(u32, u32)>,
+) -> Result<(), AppError> {
+    pixiu::delete_debt_repayment(&pool, id, repayment_id).await?;
+    Ok(())
+}
+
+async fn pixiu_get_property_info(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<PropertyRequest>,
+) -> Result<Json<Vec<pixiu::PropertyInfo>>, AppError> {
+    let base = pixiu::currency::base(params.currency.as_deref())?;
+    let properties = pixiu::get_property_info(&pool, params.archived, &base).await?;
+    Ok(Json(properties))
+}
+
+async fn pixiu_insert_property_info(
+    State(pool): State<Pool<Db>>,
+    Json(payload): Json<pixiu::PropertyInfo>,
+) -> Result<Json<u64>, AppError> {
+    let id = pixiu::insert_property_info(&pool, payload).await?;
+    Ok(Json(id))
+}
+
+async fn pixiu_update_property_info(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+    Json(payload): Json<pixiu::PropertyInfo>,
+) -> Result<(), AppError> {
+    pixiu::update_property_info(&pool, id, payload, &client(&headers)).await?;
+    Ok(())
+}
+
+async fn pixiu_archive_property_info(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<(), AppError> {
+    pixiu::archive_property_info(&pool, id, true).await?;
+    Ok(())
+}
+
+async fn pixiu_unarchive_property_info(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<(), AppError> {
+    pixiu::archive_property_info(&pool, id, false).await?;
+    Ok(())
+}
+
+async fn pixiu_get_assertions(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<AssertionRequest>,
+) -> Result<Json<Vec<pixiu::reconcile::BalanceAssertion>>, AppError> {
+    let assertions = pixiu::reconcile::get_assertions(&pool, params.source).await?;
+    Ok(Json(assertions))
+}
+
+/// 记录余额断言，返回账本余额和差额
+async fn pixiu_insert_assertion(
+    State(pool): State<Pool<Db>>,
+    Json(payload): Json<pixiu::reconcile::BalanceAssertion>,
+) -> Result<Json<pixiu::reconcile::BalanceAssertion>, AppError> {
+    let assertion = pixiu::reconcile::insert_assertion(&pool, payload).await?;
+    Ok(Json(assertion))
+}
+
+async fn pixiu_delete_assertion(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<(), AppError> {
+    pixiu::reconcile::delete_assertion(&pool, id).await?;
+    Ok(())
+}
+
+async fn pixiu_get_exchange_rates(
+    State(pool): State<Pool<Db>>,
+) -> Result<Json<Vec<pixiu::currency::ExchangeRate>>, AppError> {
+    let rates = pixiu::currency::get_exchange_rates(&pool).await?;
+    Ok(Json(rates))
+}
+
+async fn pixiu_insert_exchange_rate(
+    State(pool): State<Pool<Db>>,
+    Json(payload): Json<pixiu::currency::ExchangeRate>,
+) -> Result<Json<u64>, AppError> {
+    let id = pixiu::currency::insert_exchange_rate(&pool, payload).await?;
+    Ok(Json(id))
+}
+
+async fn pixiu_delete_exchange_rate(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+) -> Result<(), AppError> {
+    pixiu::currency::delete_exchange_rate(&pool, id).await?;
+    Ok(())
+}
+
+/// 请求体为汇率 CSV 文件内容
+async fn pixiu_import_exchange_rates(
+    State(pool): State<Pool<Db>>,
+    body: Bytes,
+) -> Result<Json<usize>, AppError> {
+    let count = pixiu::currency::import_exchange_rates(&pool, &body).await?;
+    Ok(Json(count))
+}
+
+async fn pixiu_get_net_worth(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<RangeRequest>,
+) -> Result<Json<Vec<pixiu::net_worth::NetWorth>>, AppError> {
+    let history = pixiu::net_worth::get_history(&pool, params.from, params.to).await?;
+    Ok(Json(history))
+}
+
+/// 立即记录当天的净资产快照
+async fn pixiu_snapshot_net_worth(State(pool): State<Pool<Db>>) -> Result<(), AppError> {
+    pixiu::net_worth::snapshot(&pool, chrono::Utc::now().timestamp()).await?;
+    Ok(())
+}
+
+async fn pixiu_get_net_worth_chart(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<RangeRequest>,
+) -> Result<Response, AppError> {
+    let history = pixiu::net_worth::get_history(&pool, params.from, params.to).await?;
+    let png = pixiu::net_worth::chart(&history)?;
+    Ok(([(header::CONTENT_TYPE, "image/png")], png).into_response())
+}
+
+async fn pixiu_get_fund_sources(
+    State(pool): State<Pool<Db>>,
+) -> Result<Json<Vec<String>>, AppError> {
+    let sources = pixiu::get_fund_sources(&pool).await?;
+    Ok(Json(sources))
+}
+
+async fn pixiu_get_fund_types(State(pool): State<Pool<Db>>) -> Result<Json<Vec<String>>, AppError> {
+    let types = pixiu::get_fund_types(&pool).await?;
+    Ok(Json(types))
+}
+
+async fn pixiu_delete_fund_info(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<(), AppError> {
+    pixiu::delete_fund_info(&pool, id, &client(&headers)).await?;
+    Ok(())
+}
+
+async fn pixiu_update_fund_info(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+    Json(payload): Json<pixiu::FundInfo>,
+) -> Result<(), AppError> {
+    pixiu::update_fund_info(&pool, id, payload, &client(&headers)).await?;
+    Ok(())
+}
+
+/// 批量新增、修改、删除，在一个事务中执行
+async fn pixiu_batch_fund_info(
+    State(pool): State<Pool<Db>>,
+    headers: HeaderMap,
+    Json(payload): Json<Vec<pixiu::batch::Operation>>,
+) -> Result<Json<pixiu::batch::BatchResult>, AppError> {
+    let result = pixiu::batch::execute(&pool, payload, &client(&headers)).await?;
+    Ok(Json(result))
+}
+
+async fn pixiu_restore_fund_info(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<(), AppError> {
+    pixiu::restore_fund_info(&pool, id, &client(&headers)).await?;
+    Ok(())
+}
+
+async fn pixiu_get_fund_audits(
+    State(pool): State<Pool<Db>>,
+    Query(params): Query<AuditRequest>,
+) -> Result<Json<Vec<pixiu::audit::AuditEntry>>, AppError> {
+    let limit = params.limit.unwrap_or(50);
+    let audits = pixiu::audit::get_audits(&pool, params.fund_id, limit).await?;
+    Ok(Json(audits))
+}
+
+async fn pixiu_revert_fund_audit(
+    State(pool): State<Pool<Db>>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<(), AppError> {
+    pixiu::audit::revert(&pool, id, &client(&headers)).await?;
+    Ok(())
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct PageRequest {
+    from: i64,
+    to: i64,
+    /// 页码从 1 开始，与 `cursor` 只能指定一个
+    page: Option<u32>,
+    size: u32,
+    /// 上一页返回的 `next_cursor`
+    cursor: Option<String>,
+    #[serde(default)]
+    sort: pixiu::page::SortField,
+    #[serde(default)]
+    order: pixiu::page::Order,
+    source: Option<String>,
+    #[serde(rename = "type")]
+    fund_type: Option<String>,
+    name: Option<String>,
+    /// 逗号分隔的标签，带有任一标签即可
+    tags: Option<String>,
+    /// 分类统计汇总到的层级，顶级为 1，不指定时按记录的分类统计
+    level: Option<usize>,
+    /// 统计金额的币种，默认人民币
+    currency: Option<String>,
+}
+
+impl PageRequest {
+    fn page(&self) -> anyhow::Result<pixiu::page::Page> {
+        if self.from > self.to {
+            return Err(StatusError::bad_request("from is after to").into());
+        }
+        pixiu::page::Page::new(
+            self.page,
+            self.size,
+            self.cursor.as_deref(),
+            self.sort,
+            self.order,
+        )
+    }
+
+    fn filter(&self) -> pixiu::FundFilter {
+        pixiu::FundFilter::new(
+            self.from,
+            self.to,
+            self.source.clone(),
+            self.fund_type.clone(),
+            self.name.clone(),
+            self.tags.clone(),
+        )
+    }
+}
+
+/// 导出参数，筛选条件与 `PageRequest` 相同
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct ExportRequest {
+    from: i64,
+    to: i64,
+    source: Option<String>,
+    #[serde(rename = "type")]
+    fund_type: Option<String>,
+    name: Option<String>,
+    tags: Option<String>,
+    format: pixiu::export::ExportFormat,
+}
+
+/// 收支趋势参数，筛选条件与 `PageRequest` 相同
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct ReportRequest {
+    from: i64,
+    to: i64,
+    source: Option<String>,
+    #[serde(rename = "type")]
+    fund_type: Option<String>,
+    name: Option<String>,
+    tags: Option<String>,
+    period: pixiu::report::Period,
+    split: Option<pixiu::report::Split>,
+    currency: Option<String>,
+}
+
+/// 变更记录查询参数，默认返回最近 50 条
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct AuditRequest {
+    fund_id: Option<u32>,
+    limit: Option<u32>,
+}
+
+/// 标签统计参数，筛选条件与 `PageRequest` 相同
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct TagSumRequest {
+    from: i64,
+    to: i64,
+    source: Option<String>,
+    #[serde(rename = "type")]
+    fund_type: Option<String>,
+    name: Option<String>,
+    tags: Option<String>,
+    currency: Option<String>,
+}
+
+/// 账单导入参数，`source` 为空时按平台自动设置
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct ImportRequest {
+    platform: pixiu::import::Platform,
+    source: Option<String>,
+}
+
+/// 预算查询参数，默认当前月份
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct BudgetRequest {
+    timestamp: Option<i64>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct RangeRequest {
+    from: i64,
+    to: i64,
+}
+
+/// 还款计划参数，同时给出金额和时间时计算提前还款
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct ScheduleRequest {
+    prepay_amount: Option<rust_decimal::Decimal>,
+    prepay_timestamp: Option<i64>,
+    #[serde(default)]
+    keep: pixiu::loan::PrepayKeep,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct PropertyRequest {
+    #[serde(default)]
+    archived: bool,
+    /// `base_amount` 的币种，默认人民币
+    currency: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct AssertionRequest {
+    source: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct PageResponse<T> {
+    total: i32,
+    data: Vec<T>,
+    /// 下一页的游标，没有下一页时为空
+    next_cursor: Option<String>,
+    #[serde(flatten)]
+    summary: pixiu::Summary,
+}
@@ -0,0 +1,177 @@
+use crate::storage::Dialect;
+
+type Db = sqlx::Sqlite;
+const DIALECT: Dialect = Dialect::Sqlite;
+
+/// 记账接口的 SQLite 实现，与 MySQL 共用 `routes.rs` 及 `pixiu` 的代码
+#[allow(clippy::duplicate_mod)]
+#[path = "routes.rs"]
+pub mod routes;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use rust_decimal::Decimal;
+    use serde_json::json;
+    use sqlx::{QueryBuilder, SqlitePool};
+
+    use super::routes::pixiu::{self, page, FundFilter, FundInfo};
+    use crate::storage::{Database, Money};
+
+    #[tokio::test]
+    async fn test_fund_round_trip() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        crate::migrate::run(&db).await.unwrap();
+        let Database::Sqlite(pool) = db else {
+            unreachable!()
+        };
+        let fund: FundInfo = serde_json::from_value(json!({
+            "id": null,
+            "amount": -12.34,
+            "name": "午饭",
+            "class": "餐饮美食",
+            "timestamp": 1704011400,
+            "source": "支付宝",
+            "currency": "CNY",
+            "tags": ["工作日"],
+        }))
+        .unwrap();
+        pixiu::insert_fund_info(&pool, fund, "test").await.unwrap();
+
+        let filter = FundFilter::new(0, i64::MAX, None, None, Some("午".to_string()), None);
+        let page = page::Page::new(None, 10, None, Default::default(), Default::default()).unwrap();
+        let funds = pixiu::get_fund_info(&pool, &filter, &page).await.unwrap();
+        let funds = serde_json::to_value(&funds).unwrap();
+        assert_eq!(funds[0]["amount"], json!(-12.34));
+        assert_eq!(funds[0]["name"], "午饭");
+        assert_eq!(funds[0]["tags"], json!(["工作日"]));
+
+        let summary = pixiu::get_summary(&pool, &filter, None, "CNY")
+            .await
+            .unwrap();
+        let summary = serde_json::to_value(&summary).unwrap();
+        assert_eq!(summary["expenses"], json!(-12.34));
+
+        let id = funds[0]["id"].as_u64().unwrap() as u32;
+        pixiu::delete_fund_info(&pool, id, "test").await.unwrap();
+        assert_eq!(pixiu::count(&pool, &filter).await.unwrap(), 0);
+        pixiu::restore_fund_info(&pool, id, "test").await.unwrap();
+        assert_eq!(pixiu::count(&pool, &filter).await.unwrap(), 1);
+    }
+
+    /// 列表筛选条件的查询计划，每行为使用的表及索引
+    async fn query_plan(pool: &SqlitePool, filter: &FundFilter) -> Vec<String> {
+        let mut qb = QueryBuilder::new("EXPLAIN QUERY PLAN SELECT * FROM pixiu_fund_info");
+        filter.push_where(&mut qb);
+        let rows: Vec<(i64, i64, i64, String)> = qb.build_query_as().fetch_all(pool).await.unwrap();
+        rows.into_iter().map(|(_, _, _, detail)| detail).collect()
+    }
+
+    #[tokio::test]
+    async fn test_filter_uses_indexes() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        crate::migrate::run(&db).await.unwrap();
+        let Database::Sqlite(pool) = db else {
+            unreachable!()
+        };
+        let all = FundFilter::new(0, 10, None, None, None, None);
+        let by_source = FundFilter::new(0, 10, Some("支付宝".to_string()), None, None, None);
+        let by_class = FundFilter::new(0, 10, None, Some("餐饮美食".to_string()), None, None);
+        let cases = [
+            (all, "pixiu_fund_info_deleted_timestamp"),
+            (by_source, "pixiu_fund_info_source_timestamp"),
+            (by_class, "pixiu_fund_split_class"),
+        ];
+        for (filter, index) in cases {
+            let plan = query_plan(&pool, &filter).await;
+            assert!(
+                plan.iter()
+                    .any(|detail| detail.contains(&format!("USING INDEX {index} "))),
+                "{index} is not used: {plan:?}"
+            );
+            assert!(
+                !plan
+                    .iter()
+                    .any(|detail| detail.starts_with("SCAN pixiu_fund_info")),
+                "full scan: {plan:?}"
+            );
+        }
+    }
+
+    /// 列表接口在 10 万条记录上的耗时，数据库为临时目录下的 SQLite 文件：
+    ///
+    /// `cargo test --release bench_fund_list -- --ignored --nocapture`
+    #[tokio::test]
+    #[ignore]
+    async fn bench_fund_list() {
+        const ROWS: i64 = 100_000;
+        const RUNS: usize = 20;
+        const START: i64 = 1_640_995_200;
+        let path = std::env::temp_dir().join("pixiu_bench.db");
+        let _ = std::fs::remove_file(&path);
+        let db = Database::connect(&format!("sqlite:{}", path.display()))
+            .await
+            .unwrap();
+        crate::migrate::run(&db).await.unwrap();
+        let Database::Sqlite(pool) = db else {
+            unreachable!()
+        };
+        let classes = ["餐饮美食", "交通出行", "日用百货", "工资", "转账红包"];
+        let sources = ["支付宝", "微信", "招商银行"];
+        let mut tx = pool.begin().await.unwrap();
+        for from in (0..ROWS).step_by(1000) {
+            let mut qb = QueryBuilder::new(
+                "INSERT INTO pixiu_fund_info (amount, name, class, timestamp, source, currency) ",
+            );
+            // 三年内均匀分布，每 50 条一笔收入
+            qb.push_values(from..from + 1000, |mut b, i| {
+                let amount = match i % 50 {
+                    0 => Decimal::new(800_000, 2),
+                    n => Decimal::new(-(n * 137 % 20_000), 2),
+                };
+                b.push_bind(Money(amount))
+                    .push_bind(format!("bench {i}"))
+                    .push_bind(classes[i as usize % classes.len()])
+                    .push_bind(START + i * 946)
+                    .push_bind(sources[i as usize % sources.len()])
+                    .push_bind("CNY");
+            });
+            qb.build().execute(&mut *tx).await.unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        let filter = FundFilter::new(START, START + ROWS * 946, None, None, None, None);
+        let page = page::Page::new(Some(1), 20, None, Default::default(), Default::default());
+        let page = page.unwrap();
+        let mut sequential = vec![];
+        let mut concurrent = vec![];
+        for _ in 0..RUNS {
+            let start = Instant::now();
+            pixiu::count(&pool, &filter).await.unwrap();
+            pixiu::get_fund_info(&pool, &filter, &page).await.unwrap();
+            pixiu::get_summary(&pool, &filter, None, "CNY")
+                .await
+                .unwrap();
+            sequential.push(start.elapsed());
+
+            let start = Instant::now();
+            tokio::try_join!(
+                pixiu::count(&pool, &filter),
+                pixiu::get_fund_info(&pool, &filter, &page),
+                pixiu::get_summary(&pool, &filter, None, "CNY"),
+            )
+            .unwrap();
+            concurrent.push(start.elapsed());
+        }
+        sequential.sort();
+        concurrent.sort();
+        println!(
+            "{ROWS} rows, median of {RUNS} runs: sequential {:?}, concurrent {:?}",
+            sequential[RUNS / 2],
+            concurrent[RUNS / 2]
+        );
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,9 +1,10 @@
-use sqlx::MySqlPool;
-
-use crate::utils;
+use crate::{
+    storage::{Market, Repository},
+    utils,
+};
 
 /// 获取最新的黄金价格
-pub async fn obtain(pool: &MySqlPool) -> anyhow::Result<()> {
+pub async fn obtain(repo: &impl Repository) -> anyhow::Result<()> {
     let cli = reqwest::Client::new();
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
@@ -20,48 +21,22 @@ pub async fn obtain(pool: &MySqlPool) -> anyhow::Result<()> {
     let resp = cli.get(url).headers(headers).send().await?.text().await?;
     let resp = resp.replace("var quote_json = ", "");
     let rsp: Rsp = serde_json::from_str(&resp)?;
-    save(pool, &rsp.data.prices).await?;
-    Ok(())
-}
-
-async fn save(pool: &MySqlPool, prices: &Vec<Price>) -> anyhow::Result<()> {
-    if prices.len() == 0 {
-        return Ok(());
-    }
-    let mut query = String::from("insert ignore into gold_info (timestamp, price) values ");
-    let params: Vec<String> = prices.iter().map(|_| format!("(?, ?)")).collect();
-    query.push_str(&params.join(", "));
-    let mut q = sqlx::query(&query);
-    for price in prices {
-        q = q.bind(price.time).bind(price.q1);
-    }
-
-    q.execute(pool).await?;
+    let prices: Vec<(i64, f32)> = rsp.data.prices.iter().map(|p| (p.time, p.q1)).collect();
+    repo.save_prices(Market::Gold, &prices).await?;
     Ok(())
 }
 
 /// 获取黄金价格
-pub async fn get_info(pool: &MySqlPool) -> anyhow::Result<(Vec<String>, Vec<f32>)> {
-    let gold_info_list = sqlx::query_as!(
-        GoldInfoPO,
-        "select * from (select * from gold_info order by timestamp desc limit 365) t order by timestamp",
-    )
-    .fetch_all(pool)
-    .await?;
+pub async fn get_info(repo: &impl Repository) -> anyhow::Result<(Vec<String>, Vec<f32>)> {
+    let gold_info_list = repo.latest_prices(Market::Gold, 365).await?;
     let (mut dates, mut prices) = (vec![], vec![]);
-    for gold_info in gold_info_list {
-        dates.push(utils::timestamp2time(gold_info.timestamp / 1000, "%m-%d"));
-        prices.push(gold_info.price);
+    for (timestamp, price) in gold_info_list {
+        dates.push(utils::timestamp2time(timestamp / 1000, "%m-%d"));
+        prices.push(price);
     }
     Ok((dates, prices))
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct GoldInfoPO {
-    timestamp: i64,
-    price: f32,
-}
-
 #[derive(Debug, serde::Deserialize)]
 struct Price {
     q1: f32,
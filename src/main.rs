@@ -1,5 +1,4 @@
-use log::info;
-use sqlx::MySqlPool;
+use log::{error, info};
 use tokio::net::TcpListener;
 use tokio_cron_scheduler::{Job, JobScheduler};
 
@@ -7,9 +6,11 @@ mod api;
 mod email;
 mod gold;
 mod leetcode;
+mod migrate;
 mod news;
 mod saying;
 mod stock;
+mod storage;
 mod utils;
 mod weather;
 
@@ -19,7 +20,8 @@ const STOCK_INFO_IMG_NAME: &str = "sse_line.png";
 /// 检查运行环境（环境变量）
 ///
 /// EMAIL_AUTHORIZE_CODE 用于发送邮件
-/// DATABASE_URL 用于连接数据库
+/// DATABASE_URL 用于连接数据库，`mysql://` 为 MySQL，`sqlite:` 为 SQLite
+/// MARKET_JOBS 为 1 时抓取新闻、行情并发送每日速递，可不设置
 fn check_runtime_environment() {
     dotenv::dotenv().ok(); // 从 .env 加载环境变量，开发环境用
     std::env::var("EMAIL_AUTHORIZE_CODE").expect("Cannot get env EMAIL_AUTHORIZE_CODE");
@@ -32,67 +34,114 @@ async fn main() -> anyhow::Result<()> {
     check_runtime_environment(); // 检查运行环境
 
     let database_url = std::env::var("DATABASE_URL").unwrap();
-    let pool: MySqlPool = MySqlPool::connect(&database_url).await?;
-    // news::obtain_latest_news(&pool)
-    //     .await
-    //     .expect("failed to obtain latest news");
-    // gold::obtain(&pool).await?;
-    // stock::obtain(&pool).await?;
-    // send_email(&pool).await.unwrap();
+    let db = storage::Database::connect(&database_url).await?;
+    migrate::run(&db).await?;
     utils::send_message("启动成功").await?;
 
-    // let sched = JobScheduler::new().await?;
-    // let pool1 = pool.clone();
-    // let pool2 = pool.clone();
-    // sched
-    //     .add(Job::new_async("0 * * * * *", move |_uuid, mut _l| {
-    //         let pool = pool1.clone();
-    //         Box::pin(async move {
-    //             info!(
-    //                 "Start Obtain news !!! Current time: {}",
-    //                 utils::currenttime()
-    //             );
-    //             news::obtain_latest_news(&pool).await.unwrap();
-    //         })
-    //     })?)
-    //     .await?;
-    // sched
-    //     .add(Job::new_async("0 0 0 * * *", move |_uuid, mut _l| {
-    //         let pool = pool2.clone();
-    //         Box::pin(async move {
-    //             info!(
-    //                 "Start schedule !!! Current time: {:?}",
-    //                 std::time::SystemTime::now()
-    //             );
-    //             gold::obtain(&pool).await.expect("obtain gold info failed!");
-    //             stock::obtain(&pool)
-    //                 .await
-    //                 .expect("obtain stock info failed!");
-    //             send_email(&pool).await.expect("send email failed!");
-    //         })
-    //     })?)
-    //     .await?;
-    // sched.start().await?;
+    // 每小时生成到期的周期记账
+    let sched = JobScheduler::new().await?;
+    let recurring_db = db.clone();
+    sched
+        .add(Job::new_async("0 0 * * * *", move |_uuid, mut _l| {
+            let db = recurring_db.clone();
+            Box::pin(async move {
+                let now = chrono::Utc::now().timestamp();
+                if let Err(err) = api::post_recurring(&db, now).await {
+                    error!("post recurring funds failed: {err:#}");
+                }
+            })
+        })?)
+        .await?;
+    // 每天 23:55（上海时间）记录资产与负债快照，定时任务按 UTC 计算
+    let net_worth_db = db.clone();
+    sched
+        .add(Job::new_async("0 55 15 * * *", move |_uuid, mut _l| {
+            let db = net_worth_db.clone();
+            Box::pin(async move {
+                let now = chrono::Utc::now().timestamp();
+                if let Err(err) = api::snapshot_net_worth(&db, now).await {
+                    error!("snapshot net worth failed: {err:#}");
+                }
+            })
+        })?)
+        .await?;
+    // 每天 04:00（上海时间）彻底删除 30 天前删除的资金记录
+    let purge_db = db.clone();
+    sched
+        .add(Job::new_async("0 0 20 * * *", move |_uuid, mut _l| {
+            let db = purge_db.clone();
+            Box::pin(async move {
+                let before = chrono::Utc::now().timestamp() - 30 * 24 * 60 * 60;
+                if let Err(err) = api::purge_deleted(&db, before).await {
+                    error!("purge deleted funds failed: {err:#}");
+                }
+            })
+        })?)
+        .await?;
+    if std::env::var("MARKET_JOBS").is_ok_and(|value| value == "1") {
+        add_market_jobs(&sched, &db).await?;
+    }
+    sched.start().await?;
 
     info!("Main thread start !!!");
 
     // 使主线程保持阻塞直到用户手动终止（例如按 Ctrl+C）
     // tokio::signal::ctrl_c().await?;
 
-    let app = api::app(pool.clone());
+    let app = api::app(db);
     // run it with hyper on localhost:3000
     let listener = TcpListener::bind("0.0.0.0:3000").await.unwrap();
     axum::serve(listener, app).await.unwrap();
     Ok(())
 }
 
-async fn send_email(pool: &MySqlPool) -> anyhow::Result<()> {
+/// 每分钟抓取新闻，每天抓取黄金、上证指数并发送每日速递邮件
+async fn add_market_jobs(sched: &JobScheduler, db: &storage::Database) -> anyhow::Result<()> {
+    let news_db = db.clone();
+    sched
+        .add(Job::new_async("0 * * * * *", move |_uuid, mut _l| {
+            let db = news_db.clone();
+            Box::pin(async move {
+                info!(
+                    "Start Obtain news !!! Current time: {}",
+                    utils::currenttime()
+                );
+                if let Err(err) = news::obtain_latest_news(&db).await {
+                    error!("obtain news failed: {err:#}");
+                }
+            })
+        })?)
+        .await?;
+    let daily_db = db.clone();
+    sched
+        .add(Job::new_async("0 0 0 * * *", move |_uuid, mut _l| {
+            let db = daily_db.clone();
+            Box::pin(async move {
+                info!(
+                    "Start schedule !!! Current time: {:?}",
+                    std::time::SystemTime::now()
+                );
+                let result = async {
+                    gold::obtain(&db).await?;
+                    stock::obtain(&db).await?;
+                    send_email(&db).await
+                };
+                if let Err(err) = result.await {
+                    error!("daily market email failed: {err:#}");
+                }
+            })
+        })?)
+        .await?;
+    Ok(())
+}
+
+async fn send_email(repo: &impl storage::Repository) -> anyhow::Result<()> {
     let (lc_name, lc_href) = leetcode::daily_question()
         .await
         .expect("get leetcode question failed");
-    let gold = gold::get_info(pool).await?;
+    let gold = gold::get_info(repo).await?;
     utils::create_line_img("Gold Info", "RMB", GOLD_INFO_IMG_NAME, gold.0, gold.1)?;
-    let stock = stock::get_info(pool).await?;
+    let stock = stock::get_info(repo).await?;
     utils::create_line_img("SSE Index", "Point", STOCK_INFO_IMG_NAME, stock.0, stock.1)?;
     let weathers = weather::get().await.expect("get weather failed");
 
@@ -145,19 +194,6 @@ async fn send_email(pool: &MySqlPool) -> anyhow::Result<()> {
 //     news_content + "</ul>"
 // }
 
-fn concat_comic(comics: Vec<(String, String)>) -> String {
-    if comics.is_empty() {
-        return "".to_owned();
-    }
-    "<h2>漫画</h2><ul>".to_owned()
-        + &comics
-            .into_iter()
-            .map(|(name, chapter_name)| format!(r#"<li>{} {}</li>"#, name, chapter_name))
-            .collect::<Vec<_>>()
-            .join("")
-        + "</ul>"
-}
-
 fn concat_weather(weathers: Vec<String>) -> String {
     if weathers.is_empty() {
         return "".to_owned();
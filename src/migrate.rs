@@ -0,0 +1,726 @@
+use log::info;
+use sqlx::MySqlPool;
+
+use crate::storage::{on_pool, Database};
+
+/// 表结构的版本，启动时按顺序执行未执行过的版本
+///
+/// 已发布的版本不能修改，表结构有变化时追加新版本
+const MIGRATIONS: [(u32, &str); 19] = [
+    (1, "news, gold_info and stock_info"),
+    (2, "pixiu tables"),
+    (3, "debt loan terms"),
+    (4, "debt repayments"),
+    (5, "property opening balance"),
+    (6, "currencies"),
+    (7, "transfers"),
+    (8, "budgets"),
+    (9, "recurring funds"),
+    (10, "net worth history"),
+    (11, "tags"),
+    (12, "categories"),
+    (13, "fund splits"),
+    (14, "rules"),
+    (15, "attachments"),
+    (16, "soft delete and audit log"),
+    (17, "reconciliation"),
+    (18, "money columns as DECIMAL"),
+    (19, "fund list indexes"),
+];
+
+/// 执行一个版本的变更
+///
+/// 没有版本记录的旧库可能已有部分表和列，建表用 `IF NOT EXISTS`，加列前先检查列是否存在
+async fn apply(pool: &MySqlPool, version: u32) -> anyhow::Result<()> {
+    match version {
+        1 => {
+            execute_all(
+                pool,
+                &[
+                    "CREATE TABLE IF NOT EXISTS news (
+                        id INT UNSIGNED NOT NULL,
+                        content TEXT NOT NULL COMMENT '内容',
+                        timestamp BIGINT NOT NULL COMMENT '时间戳',
+                        target VARCHAR(128) NOT NULL COMMENT '链接',
+                        PRIMARY KEY (id),
+                        KEY time (timestamp) USING BTREE
+                    ) ENGINE = InnoDB",
+                    "CREATE TABLE IF NOT EXISTS gold_info (
+                        timestamp BIGINT NOT NULL COMMENT '时间戳',
+                        price FLOAT NOT NULL COMMENT '价格',
+                        PRIMARY KEY (timestamp)
+                    )",
+                    "CREATE TABLE IF NOT EXISTS stock_info (
+                        timestamp BIGINT NOT NULL COMMENT '时间戳',
+                        price FLOAT NOT NULL COMMENT '价格',
+                        PRIMARY KEY (timestamp)
+                    )",
+                ],
+            )
+            .await
+        }
+        2 => {
+            execute_all(
+                pool,
+                &[
+                    "CREATE TABLE IF NOT EXISTS pixiu_fund_info (
+                        id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                        name VARCHAR(255) NOT NULL,
+                        amount FLOAT NOT NULL,
+                        class VARCHAR(255) NOT NULL,
+                        timestamp BIGINT NOT NULL,
+                        source VARCHAR(255) NOT NULL
+                    )",
+                    "CREATE TABLE IF NOT EXISTS pixiu_debt_info (
+                        id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                        name VARCHAR(255) NOT NULL,
+                        amount FLOAT NOT NULL,
+                        repayment FLOAT NOT NULL,
+                        last_timestamp BIGINT NOT NULL
+                    )",
+                    "CREATE TABLE IF NOT EXISTS pixiu_property_info (
+                        id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                        name VARCHAR(255) NOT NULL,
+                        amount FLOAT NOT NULL
+                    )",
+                ],
+            )
+            .await
+        }
+        3 => {
+            let sql = "ALTER TABLE pixiu_debt_info
+                ADD COLUMN principal DECIMAL(15, 2) NULL,
+                ADD COLUMN annual_rate FLOAT NULL,
+                ADD COLUMN term_months INT UNSIGNED NULL,
+                ADD COLUMN method VARCHAR(32) NULL,
+                ADD COLUMN start_timestamp BIGINT NULL";
+            add_column(pool, "pixiu_debt_info", "principal", sql).await
+        }
+        4 => {
+            let sql = "CREATE TABLE IF NOT EXISTS pixiu_debt_repayment (
+                id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                debt_id INT UNSIGNED NOT NULL,
+                amount DECIMAL(15, 2) NOT NULL,
+                timestamp BIGINT NOT NULL,
+                KEY debt_id (debt_id),
+                FOREIGN KEY (debt_id) REFERENCES pixiu_debt_info (id) ON DELETE CASCADE
+            )";
+            sqlx::query(sql).execute(pool).await?;
+            // 旧表直接保存了已还金额，转为一条还款记录后删除这两列
+            if column_exists(pool, "pixiu_debt_info", "repayment").await? {
+                execute_all(
+                    pool,
+                    &[
+                        "INSERT INTO pixiu_debt_repayment (debt_id, amount, timestamp)
+                        SELECT id, repayment, last_timestamp FROM pixiu_debt_info
+                        WHERE repayment <> 0",
+                        "ALTER TABLE pixiu_debt_info
+                        DROP COLUMN repayment, DROP COLUMN last_timestamp",
+                    ],
+                )
+                .await?;
+            }
+            Ok(())
+        }
+        // 旧表的 amount 即期初余额，期初时间为 0 表示统计全部资金记录
+        5 => {
+            if column_exists(pool, "pixiu_property_info", "amount").await? {
+                let sql = "ALTER TABLE pixiu_property_info
+                    CHANGE amount opening_balance DECIMAL(15, 2) NOT NULL,
+                    ADD COLUMN opening_timestamp BIGINT NOT NULL DEFAULT 0,
+                    ADD COLUMN archived BOOLEAN NOT NULL DEFAULT FALSE";
+                sqlx::query(sql).execute(pool).await?;
+            }
+            Ok(())
+        }
+        6 => {
+            let sql =
+                "ALTER TABLE pixiu_fund_info ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'CNY'";
+            add_column(pool, "pixiu_fund_info", "currency", sql).await?;
+            let sql = "ALTER TABLE pixiu_property_info
+                ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'CNY'";
+            add_column(pool, "pixiu_property_info", "currency", sql).await?;
+            let sql = "CREATE TABLE IF NOT EXISTS pixiu_exchange_rate (
+                id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                currency VARCHAR(3) NOT NULL,
+                rate DECIMAL(20, 8) NOT NULL,
+                timestamp BIGINT NOT NULL,
+                UNIQUE KEY currency (currency, timestamp)
+            )";
+            sqlx::query(sql).execute(pool).await?;
+            Ok(())
+        }
+        7 => {
+            let sql = "CREATE TABLE IF NOT EXISTS pixiu_transfer (
+                id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                from_source VARCHAR(255) NOT NULL,
+                to_source VARCHAR(255) NOT NULL,
+                amount DECIMAL(15, 2) NOT NULL,
+                to_amount DECIMAL(15, 2) NULL,
+                fee DECIMAL(15, 2) NOT NULL DEFAULT 0,
+                timestamp BIGINT NOT NULL,
+                remark VARCHAR(255) NOT NULL DEFAULT '',
+                KEY from_source (from_source),
+                KEY to_source (to_source)
+            )";
+            sqlx::query(sql).execute(pool).await?;
+            Ok(())
+        }
+        8 => {
+            execute_all(
+                pool,
+                &["CREATE TABLE IF NOT EXISTS pixiu_budget (
+                    id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                    class VARCHAR(255) NOT NULL,
+                    source VARCHAR(255) NULL,
+                    amount DECIMAL(15, 2) NOT NULL
+                )"],
+            )
+            .await
+        }
+        9 => {
+            execute_all(
+                pool,
+                &[
+                    "CREATE TABLE IF NOT EXISTS pixiu_recurring (
+                        id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                        amount DECIMAL(15, 2) NOT NULL,
+                        name VARCHAR(255) NOT NULL,
+                        class VARCHAR(255) NOT NULL,
+                        source VARCHAR(255) NOT NULL,
+                        rule VARCHAR(32) NOT NULL,
+                        rule_value INT UNSIGNED NOT NULL,
+                        start_timestamp BIGINT NOT NULL,
+                        end_timestamp BIGINT NULL,
+                        last_posted BIGINT NULL,
+                        enabled BOOLEAN NOT NULL DEFAULT TRUE
+                    )",
+                    "CREATE TABLE IF NOT EXISTS pixiu_recurring_override (
+                        recurring_id INT UNSIGNED NOT NULL,
+                        occurrence BIGINT NOT NULL,
+                        skip BOOLEAN NOT NULL DEFAULT FALSE,
+                        amount DECIMAL(15, 2) NULL,
+                        name VARCHAR(255) NULL,
+                        class VARCHAR(255) NULL,
+                        source VARCHAR(255) NULL,
+                        PRIMARY KEY (recurring_id, occurrence),
+                        FOREIGN KEY (recurring_id) REFERENCES pixiu_recurring (id) ON DELETE CASCADE
+                    )",
+                ],
+            )
+            .await
+        }
+        10 => {
+            execute_all(
+                pool,
+                &["CREATE TABLE IF NOT EXISTS pixiu_balance_history (
+                    id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                    timestamp BIGINT NOT NULL,
+                    liability BOOLEAN NOT NULL,
+                    item_id INT UNSIGNED NOT NULL,
+                    name VARCHAR(255) NOT NULL,
+                    amount DECIMAL(15, 2) NOT NULL,
+                    UNIQUE KEY item (timestamp, liability, item_id)
+                )"],
+            )
+            .await
+        }
+        11 => {
+            execute_all(
+                pool,
+                &[
+                    "CREATE TABLE IF NOT EXISTS pixiu_tag (
+                        id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                        name VARCHAR(64) NOT NULL,
+                        UNIQUE KEY name (name)
+                    )",
+                    "CREATE TABLE IF NOT EXISTS pixiu_fund_tag (
+                        fund_id INT UNSIGNED NOT NULL,
+                        tag_id INT UNSIGNED NOT NULL,
+                        PRIMARY KEY (fund_id, tag_id),
+                        KEY tag_id (tag_id),
+                        FOREIGN KEY (fund_id) REFERENCES pixiu_fund_info (id) ON DELETE CASCADE,
+                        FOREIGN KEY (tag_id) REFERENCES pixiu_tag (id) ON DELETE CASCADE
+                    )",
+                ],
+            )
+            .await
+        }
+        12 => {
+            execute_all(
+                pool,
+                &["CREATE TABLE IF NOT EXISTS pixiu_category (
+                    id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                    name VARCHAR(255) NOT NULL,
+                    parent_id INT UNSIGNED NULL,
+                    UNIQUE KEY name (name),
+                    KEY parent_id (parent_id),
+                    FOREIGN KEY (parent_id) REFERENCES pixiu_category (id)
+                )"],
+            )
+            .await
+        }
+        13 => {
+            execute_all(
+                pool,
+                &["CREATE TABLE IF NOT EXISTS pixiu_fund_split (
+                    id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                    fund_id INT UNSIGNED NOT NULL,
+                    class VARCHAR(255) NOT NULL,
+                    amount DECIMAL(15, 2) NOT NULL,
+                    KEY fund_id (fund_id),
+                    FOREIGN KEY (fund_id) REFERENCES pixiu_fund_info (id) ON DELETE CASCADE
+                )"],
+            )
+            .await
+        }
+        14 => {
+            execute_all(
+                pool,
+                &["CREATE TABLE IF NOT EXISTS pixiu_rule (
+                    id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                    priority INT NOT NULL DEFAULT 0,
+                    pattern VARCHAR(255) NULL,
+                    regex BOOLEAN NOT NULL DEFAULT FALSE,
+                    min_amount DECIMAL(15, 2) NULL,
+                    max_amount DECIMAL(15, 2) NULL,
+                    source VARCHAR(255) NULL,
+                    set_class VARCHAR(255) NULL,
+                    set_source VARCHAR(255) NULL,
+                    set_tags VARCHAR(1024) NULL
+                )"],
+            )
+            .await
+        }
+        15 => {
+            execute_all(
+                pool,
+                &["CREATE TABLE IF NOT EXISTS pixiu_attachment (
+                    id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                    fund_id INT UNSIGNED NOT NULL,
+                    file_name VARCHAR(255) NOT NULL,
+                    content_type VARCHAR(127) NOT NULL,
+                    size BIGINT UNSIGNED NOT NULL,
+                    timestamp BIGINT NOT NULL,
+                    path VARCHAR(255) NOT NULL,
+                    KEY fund_id (fund_id),
+                    FOREIGN KEY (fund_id) REFERENCES pixiu_fund_info (id) ON DELETE CASCADE
+                )"],
+            )
+            .await
+        }
+        16 => {
+            let sql = "ALTER TABLE pixiu_fund_info ADD COLUMN deleted_at BIGINT NULL";
+            add_column(pool, "pixiu_fund_info", "deleted_at", sql).await?;
+            let sql = "CREATE TABLE IF NOT EXISTS pixiu_fund_audit (
+                id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                fund_id INT UNSIGNED NOT NULL,
+                action VARCHAR(16) NOT NULL,
+                old_value JSON NULL,
+                new_value JSON NULL,
+                timestamp BIGINT NOT NULL,
+                client VARCHAR(255) NOT NULL,
+                KEY fund_id (fund_id),
+                KEY timestamp (timestamp)
+            )";
+            sqlx::query(sql).execute(pool).await?;
+            Ok(())
+        }
+        17 => {
+            let sql =
+                "ALTER TABLE pixiu_fund_info ADD COLUMN reconciled BOOLEAN NOT NULL DEFAULT FALSE";
+            add_column(pool, "pixiu_fund_info", "reconciled", sql).await?;
+            let sql = "CREATE TABLE IF NOT EXISTS pixiu_balance_assertion (
+                id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                source VARCHAR(255) NOT NULL,
+                timestamp BIGINT NOT NULL,
+                amount DECIMAL(15, 2) NOT NULL,
+                ledger_amount DECIMAL(15, 2) NOT NULL,
+                KEY source (source, timestamp)
+            )";
+            sqlx::query(sql).execute(pool).await?;
+            Ok(())
+        }
+        18 => migrate_money_columns(pool).await,
+        19 => create_indexes(pool).await,
+        _ => anyhow::bail!("unknown schema version {version}"),
+    }
+}
+
+/// 依次执行多条语句
+async fn execute_all(pool: &MySqlPool, sqls: &[&str]) -> anyhow::Result<()> {
+    for sql in sqls {
+        sqlx::query(sql).execute(pool).await?;
+    }
+    Ok(())
+}
+
+/// 当前库中某张表是否存在某列
+async fn column_exists(pool: &MySqlPool, table: &str, column: &str) -> anyhow::Result<bool> {
+    let sql = "SELECT COUNT(*) FROM information_schema.columns
+        WHERE table_schema = DATABASE() AND table_name = ? AND column_name = ?";
+    let count: i64 = sqlx::query_scalar(sql)
+        .bind(table)
+        .bind(column)
+        .fetch_one(pool)
+        .await?;
+    Ok(count > 0)
+}
+
+/// 列不存在时执行 `sql` 加列
+async fn add_column(pool: &MySqlPool, table: &str, column: &str, sql: &str) -> anyhow::Result<()> {
+    if !column_exists(pool, table, column).await? {
+        sqlx::query(sql).execute(pool).await?;
+    }
+    Ok(())
+}
+
+/// 金额列的定义，早期版本为 FLOAT
+const MONEY_COLUMNS: [(&str, &str, &str); 13] = [
+    ("pixiu_fund_info", "amount", "DECIMAL(15, 2) NOT NULL"),
+    ("pixiu_debt_info", "amount", "DECIMAL(15, 2) NOT NULL"),
+    ("pixiu_debt_info", "principal", "DECIMAL(15, 2) NULL"),
+    ("pixiu_debt_repayment", "amount", "DECIMAL(15, 2) NOT NULL"),
+    (
+        "pixiu_property_info",
+        "opening_balance",
+        "DECIMAL(15, 2) NOT NULL",
+    ),
+    ("pixiu_transfer", "amount", "DECIMAL(15, 2) NOT NULL"),
+    ("pixiu_transfer", "to_amount", "DECIMAL(15, 2) NULL"),
+    ("pixiu_transfer", "fee", "DECIMAL(15, 2) NOT NULL DEFAULT 0"),
+    ("pixiu_budget", "amount", "DECIMAL(15, 2) NOT NULL"),
+    ("pixiu_recurring", "amount", "DECIMAL(15, 2) NOT NULL"),
+    ("pixiu_recurring_override", "amount", "DECIMAL(15, 2) NULL"),
+    ("pixiu_balance_history", "amount", "DECIMAL(15, 2) NOT NULL"),
+    ("pixiu_exchange_rate", "rate", "DECIMAL(20, 8) NOT NULL"),
+];
+
+/// 将浮点金额列改为 DECIMAL，已有数据按列的小数位四舍五入
+async fn migrate_money_columns(pool: &MySqlPool) -> anyhow::Result<()> {
+    let sql = "SELECT DATA_TYPE FROM information_schema.columns
+        WHERE table_schema = DATABASE() AND table_name = ? AND column_name = ?";
+    for (table, column, definition) in MONEY_COLUMNS {
+        let data_type: Option<String> = sqlx::query_scalar(sql)
+            .bind(table)
+            .bind(column)
+            .fetch_optional(pool)
+            .await?;
+        if matches!(data_type.as_deref(), Some("float" | "double")) {
+            let sql = format!("ALTER TABLE {table} MODIFY {column} {definition}");
+            sqlx::query(&sql).execute(pool).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 资金记录列表和统计按时间范围、来源、分类筛选时用到的索引
+const INDEXES: [(&str, &str, &str); 4] = [
+    (
+        "pixiu_fund_info",
+        "deleted_timestamp",
+        "deleted_at, timestamp",
+    ),
+    ("pixiu_fund_info", "source_timestamp", "source, timestamp"),
+    ("pixiu_fund_info", "class", "class"),
+    ("pixiu_fund_split", "class", "class"),
+];
+
+/// 给已有的表补上缺少的索引
+async fn create_indexes(pool: &MySqlPool) -> anyhow::Result<()> {
+    let sql = "SELECT COUNT(*) FROM information_schema.statistics
+        WHERE table_schema = DATABASE() AND table_name = ? AND index_name = ?";
+    for (table, name, columns) in INDEXES {
+        let count: i64 = sqlx::query_scalar(sql)
+            .bind(table)
+            .bind(name)
+            .fetch_one(pool)
+            .await?;
+        if count == 0 {
+            let sql = format!("CREATE INDEX {name} ON {table} ({columns})");
+            sqlx::query(&sql).execute(pool).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 当前版本之后待执行的版本，数据库版本比程序新时报错，避免旧程序操作新表结构
+fn pending(current: Option<u32>) -> anyhow::Result<&'static [(u32, &'static str)]> {
+    let latest = MIGRATIONS.last().map_or(0, |(version, _)| *version);
+    match current {
+        Some(current) if current > latest => {
+            anyhow::bail!("schema version {current} is newer than {latest}, upgrade the program")
+        }
+        Some(current) => Ok(&MIGRATIONS[MIGRATIONS.partition_point(|(v, _)| *v <= current)..]),
+        None => Ok(&MIGRATIONS),
+    }
+}
+
+/// SQLite 执行一个版本的语句
+///
+/// SQLite 的支持晚于这些版本，表直接建成最终的结构，只加列、改列类型的版本没有语句
+fn sqlite_statements(version: u32) -> anyhow::Result<Vec<String>> {
+    let sqls: &[&str] = match version {
+        1 => &[
+            "CREATE TABLE IF NOT EXISTS news (
+                id INTEGER NOT NULL PRIMARY KEY,
+                content TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                target TEXT NOT NULL
+            )",
+            "CREATE INDEX IF NOT EXISTS time ON news (timestamp)",
+            "CREATE TABLE IF NOT EXISTS gold_info (
+                timestamp INTEGER NOT NULL PRIMARY KEY,
+                price REAL NOT NULL
+            )",
+            "CREATE TABLE IF NOT EXISTS stock_info (
+                timestamp INTEGER NOT NULL PRIMARY KEY,
+                price REAL NOT NULL
+            )",
+        ],
+        2 => &[
+            "CREATE TABLE pixiu_fund_info (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                amount REAL NOT NULL,
+                class TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                source TEXT NOT NULL,
+                currency TEXT NOT NULL DEFAULT 'CNY',
+                deleted_at INTEGER NULL,
+                reconciled BOOLEAN NOT NULL DEFAULT FALSE
+            )",
+            "CREATE TABLE pixiu_debt_info (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                amount REAL NOT NULL,
+                principal REAL NULL,
+                annual_rate REAL NULL,
+                term_months INTEGER NULL,
+                method TEXT NULL,
+                start_timestamp INTEGER NULL
+            )",
+            "CREATE TABLE pixiu_property_info (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                opening_balance REAL NOT NULL,
+                opening_timestamp INTEGER NOT NULL DEFAULT 0,
+                archived BOOLEAN NOT NULL DEFAULT FALSE,
+                currency TEXT NOT NULL DEFAULT 'CNY'
+            )",
+        ],
+        4 => &[
+            "CREATE TABLE pixiu_debt_repayment (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                debt_id INTEGER NOT NULL REFERENCES pixiu_debt_info (id) ON DELETE CASCADE,
+                amount REAL NOT NULL,
+                timestamp INTEGER NOT NULL
+            )",
+            "CREATE INDEX pixiu_debt_repayment_debt_id ON pixiu_debt_repayment (debt_id)",
+        ],
+        6 => &["CREATE TABLE pixiu_exchange_rate (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            currency TEXT NOT NULL,
+            rate REAL NOT NULL,
+            timestamp INTEGER NOT NULL,
+            UNIQUE (currency, timestamp)
+        )"],
+        7 => &[
+            "CREATE TABLE pixiu_transfer (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                from_source TEXT NOT NULL,
+                to_source TEXT NOT NULL,
+                amount REAL NOT NULL,
+                to_amount REAL NULL,
+                fee REAL NOT NULL DEFAULT 0,
+                timestamp INTEGER NOT NULL,
+                remark TEXT NOT NULL DEFAULT ''
+            )",
+            "CREATE INDEX pixiu_transfer_from_source ON pixiu_transfer (from_source)",
+            "CREATE INDEX pixiu_transfer_to_source ON pixiu_transfer (to_source)",
+        ],
+        8 => &["CREATE TABLE pixiu_budget (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            class TEXT NOT NULL,
+            source TEXT NULL,
+            amount REAL NOT NULL
+        )"],
+        9 => &[
+            "CREATE TABLE pixiu_recurring (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                amount REAL NOT NULL,
+                name TEXT NOT NULL,
+                class TEXT NOT NULL,
+                source TEXT NOT NULL,
+                rule TEXT NOT NULL,
+                rule_value INTEGER NOT NULL,
+                start_timestamp INTEGER NOT NULL,
+                end_timestamp INTEGER NULL,
+                last_posted INTEGER NULL,
+                enabled BOOLEAN NOT NULL DEFAULT TRUE
+            )",
+            "CREATE TABLE pixiu_recurring_override (
+                recurring_id INTEGER NOT NULL REFERENCES pixiu_recurring (id) ON DELETE CASCADE,
+                occurrence INTEGER NOT NULL,
+                skip BOOLEAN NOT NULL DEFAULT FALSE,
+                amount REAL NULL,
+                name TEXT NULL,
+                class TEXT NULL,
+                source TEXT NULL,
+                PRIMARY KEY (recurring_id, occurrence)
+            )",
+        ],
+        10 => &["CREATE TABLE pixiu_balance_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp INTEGER NOT NULL,
+            liability BOOLEAN NOT NULL,
+            item_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            amount REAL NOT NULL,
+            UNIQUE (timestamp, liability, item_id)
+        )"],
+        11 => &[
+            "CREATE TABLE pixiu_tag (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE
+            )",
+            "CREATE TABLE pixiu_fund_tag (
+                fund_id INTEGER NOT NULL REFERENCES pixiu_fund_info (id) ON DELETE CASCADE,
+                tag_id INTEGER NOT NULL REFERENCES pixiu_tag (id) ON DELETE CASCADE,
+                PRIMARY KEY (fund_id, tag_id)
+            )",
+            "CREATE INDEX pixiu_fund_tag_tag_id ON pixiu_fund_tag (tag_id)",
+        ],
+        12 => &[
+            "CREATE TABLE pixiu_category (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                parent_id INTEGER NULL REFERENCES pixiu_category (id)
+            )",
+            "CREATE INDEX pixiu_category_parent_id ON pixiu_category (parent_id)",
+        ],
+        13 => &[
+            "CREATE TABLE pixiu_fund_split (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                fund_id INTEGER NOT NULL REFERENCES pixiu_fund_info (id) ON DELETE CASCADE,
+                class TEXT NOT NULL,
+                amount REAL NOT NULL
+            )",
+            "CREATE INDEX pixiu_fund_split_fund_id ON pixiu_fund_split (fund_id)",
+        ],
+        14 => &["CREATE TABLE pixiu_rule (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            priority INTEGER NOT NULL DEFAULT 0,
+            pattern TEXT NULL,
+            regex BOOLEAN NOT NULL DEFAULT FALSE,
+            min_amount REAL NULL,
+            max_amount REAL NULL,
+            source TEXT NULL,
+            set_class TEXT NULL,
+            set_source TEXT NULL,
+            set_tags TEXT NULL
+        )"],
+        15 => &[
+            "CREATE TABLE pixiu_attachment (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                fund_id INTEGER NOT NULL REFERENCES pixiu_fund_info (id) ON DELETE CASCADE,
+                file_name TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                timestamp INTEGER NOT NULL,
+                path TEXT NOT NULL
+            )",
+            "CREATE INDEX pixiu_attachment_fund_id ON pixiu_attachment (fund_id)",
+        ],
+        16 => &[
+            "CREATE TABLE pixiu_fund_audit (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                fund_id INTEGER NOT NULL,
+                action TEXT NOT NULL,
+                old_value TEXT NULL,
+                new_value TEXT NULL,
+                timestamp INTEGER NOT NULL,
+                client TEXT NOT NULL
+            )",
+            "CREATE INDEX pixiu_fund_audit_fund_id ON pixiu_fund_audit (fund_id)",
+            "CREATE INDEX pixiu_fund_audit_timestamp ON pixiu_fund_audit (timestamp)",
+        ],
+        17 => &[
+            "CREATE TABLE pixiu_balance_assertion (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                source TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                amount REAL NOT NULL,
+                ledger_amount REAL NOT NULL
+            )",
+            "CREATE INDEX pixiu_balance_assertion_source
+            ON pixiu_balance_assertion (source, timestamp)",
+        ],
+        // SQLite 的索引名在库内唯一，加上表名作前缀
+        19 => {
+            return Ok(INDEXES
+                .iter()
+                .map(|(table, name, columns)| {
+                    format!("CREATE INDEX {table}_{name} ON {table} ({columns})")
+                })
+                .collect())
+        }
+        3 | 5 | 18 => &[],
+        _ => anyhow::bail!("unknown schema version {version}"),
+    };
+    Ok(sqls.iter().map(|sql| sql.to_string()).collect())
+}
+
+/// 升级到最新的表结构，每个版本执行成功后记录版本号
+///
+/// MySQL 的 DDL 无法回滚，某个版本失败时停在上一个版本，修复后重启会从失败的版本继续；
+/// SQLite 的每个版本与版本记录在同一个事务中
+pub async fn run(db: &Database) -> anyhow::Result<()> {
+    let sql = "CREATE TABLE IF NOT EXISTS schema_version (
+        version INT UNSIGNED NOT NULL PRIMARY KEY,
+        description VARCHAR(255) NOT NULL,
+        applied_at BIGINT NOT NULL
+    )";
+    let current: Option<u32> = on_pool!(db, pool => {
+        sqlx::query(sql).execute(pool).await?;
+        let sql = "SELECT MAX(version) FROM schema_version";
+        sqlx::query_scalar(sql).fetch_one(pool).await?
+    });
+    let sql = "INSERT INTO schema_version (version, description, applied_at) VALUES (?, ?, ?)";
+    for (version, description) in pending(current)? {
+        info!("migrating schema to version {version}: {description}");
+        let now = chrono::Utc::now().timestamp();
+        match db {
+            Database::MySql(pool) => {
+                apply(pool, *version).await?;
+                let record = sqlx::query(sql).bind(version).bind(description).bind(now);
+                record.execute(pool).await?;
+            }
+            Database::Sqlite(pool) => {
+                let mut tx = pool.begin().await?;
+                for sql in sqlite_statements(*version)? {
+                    sqlx::query(&sql).execute(&mut *tx).await?;
+                }
+                let record = sqlx::query(sql).bind(version).bind(description).bind(now);
+                record.execute(&mut *tx).await?;
+                tx.commit().await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_versions() {
+        assert!(MIGRATIONS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(pending(None).unwrap(), &MIGRATIONS);
+        assert_eq!(pending(Some(1)).unwrap(), &MIGRATIONS[1..]);
+        let latest = MIGRATIONS[MIGRATIONS.len() - 1].0;
+        assert!(pending(Some(latest)).unwrap().is_empty());
+        assert!(pending(Some(latest + 1)).is_err());
+    }
+}
@@ -1,9 +1,10 @@
 use anyhow::Ok;
 use log::{debug, info};
-use sqlx::mysql::MySqlPool;
 
-pub async fn obtain_latest_news(pool: &MySqlPool) -> anyhow::Result<()> {
-    let id = get_latest_id(pool).await;
+use crate::storage::Repository;
+
+pub async fn obtain_latest_news(repo: &impl Repository) -> anyhow::Result<()> {
+    let id = get_latest_id(repo).await;
     let token = get_token().await?;
     let mut max_id = 0;
     let mut news_list: Vec<NewsPO> = vec![];
@@ -39,7 +40,7 @@ pub async fn obtain_latest_news(pool: &MySqlPool) -> anyhow::Result<()> {
             .await?;
         info!("xueqiu response: {}", content);
         let resp: Rsp = cli.get(url).headers(headers).send().await?.json().await?;
-        if resp.items.len() == 0 || resp.items[0].id <= id {
+        if resp.items.is_empty() || resp.items[0].id <= id {
             break;
         }
         max_id = resp.next_max_id;
@@ -52,7 +53,7 @@ pub async fn obtain_latest_news(pool: &MySqlPool) -> anyhow::Result<()> {
             })
         });
         for news in &news_list {
-            save_news(pool, news).await.expect("save news failed");
+            repo.save_news(news).await.expect("save news failed");
         }
     }
     Ok(())
@@ -75,11 +76,11 @@ pub async fn obtain_latest_news(pool: &MySqlPool) -> anyhow::Result<()> {
 // }
 
 #[derive(Debug, serde::Deserialize)]
-struct NewsPO {
-    id: u32,
-    content: String,
-    timestamp: i64,
-    target: String,
+pub struct NewsPO {
+    pub id: u32,
+    pub content: String,
+    pub timestamp: i64,
+    pub target: String,
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -112,23 +113,13 @@ async fn get_token() -> anyhow::Result<String> {
     Ok(token.to_string())
 }
 
-async fn save_news(pool: &MySqlPool, news: &NewsPO) -> anyhow::Result<u64> {
-    let mut query =
-        sqlx::query("insert ignore into news(id, content, timestamp, target) values (?, ?, ?, ?)");
-    query = query
-        .bind(&news.id)
-        .bind(&news.content)
-        .bind(&news.timestamp)
-        .bind(&news.target);
-    let result = query.execute(pool).await?;
-    Ok(result.rows_affected())
-}
-
-async fn get_latest_id(pool: &MySqlPool) -> u32 {
-    let id: u32 = sqlx::query_scalar("select id from news order by id desc limit 1")
-        .fetch_one(pool)
+async fn get_latest_id(repo: &impl Repository) -> u32 {
+    let id = repo
+        .latest_news_id()
         .await
-        .unwrap_or_else(|_| 3812705);
+        .ok()
+        .flatten()
+        .unwrap_or(3812705);
     debug!("latest id: {id}");
     id
 }
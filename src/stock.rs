@@ -1,9 +1,11 @@
 use chrono::Utc;
-use sqlx::MySqlPool;
 
-use crate::utils;
+use crate::{
+    storage::{Market, Repository},
+    utils,
+};
 
-pub async fn obtain(pool: &MySqlPool) -> anyhow::Result<()> {
+pub async fn obtain(repo: &impl Repository) -> anyhow::Result<()> {
     let token = get_token().await?;
     // 获取当前时间戳
     let timestamp = Utc::now().timestamp_millis();
@@ -29,47 +31,20 @@ pub async fn obtain(pool: &MySqlPool) -> anyhow::Result<()> {
             stocks.push((*timestamp as i64, *price));
         }
     }
-    save(pool, &stocks).await?;
+    repo.save_prices(Market::Stock, &stocks).await?;
     Ok(())
 }
 
-async fn save(pool: &MySqlPool, prices: &Vec<(i64, f32)>) -> anyhow::Result<()> {
-    if prices.len() == 0 {
-        return Ok(());
-    }
-    let mut query = String::from("insert ignore into stock_info (timestamp, price) values ");
-    let params: Vec<String> = prices.iter().map(|_| format!("(?, ?)")).collect();
-    query.push_str(&params.join(", "));
-    let mut q = sqlx::query(&query);
-    for price in prices {
-        q = q.bind(price.0).bind(price.1);
-    }
-
-    q.execute(pool).await?;
-    Ok(())
-}
-
-pub async fn get_info(pool: &MySqlPool) -> anyhow::Result<(Vec<String>, Vec<f32>)> {
-    let gold_info_list = sqlx::query_as!(
-            StockInfoPO,
-            "select * from (select * from stock_info order by timestamp desc limit 365) t order by timestamp",
-        )
-        .fetch_all(pool)
-        .await?;
+pub async fn get_info(repo: &impl Repository) -> anyhow::Result<(Vec<String>, Vec<f32>)> {
+    let stock_info_list = repo.latest_prices(Market::Stock, 365).await?;
     let (mut dates, mut prices) = (vec![], vec![]);
-    for gold_info in gold_info_list {
-        dates.push(utils::timestamp2time(gold_info.timestamp / 1000, "%m-%d"));
-        prices.push(gold_info.price);
+    for (timestamp, price) in stock_info_list {
+        dates.push(utils::timestamp2time(timestamp / 1000, "%m-%d"));
+        prices.push(price);
     }
     Ok((dates, prices))
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct StockInfoPO {
-    timestamp: i64,
-    price: f32,
-}
-
 #[derive(Debug, serde::Deserialize)]
 struct Rsp {
     data: Data,
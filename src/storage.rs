@@ -0,0 +1,437 @@
+use std::{future::Future, str::FromStr};
+
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    mysql::{MySqlQueryResult, MySqlTypeInfo, MySqlValueRef},
+    sqlite::{
+        SqliteArgumentValue, SqliteConnectOptions, SqlitePoolOptions, SqliteQueryResult,
+        SqliteTypeInfo, SqliteValueRef,
+    },
+    Decode, Encode, MySql, MySqlPool, Sqlite, SqlitePool, Type, TypeInfo, ValueRef,
+};
+
+use crate::news::NewsPO;
+
+/// 行情、新闻的存储，MySQL 和 SQLite 共用一个实现，写法不同的 SQL 由 `Dialect` 给出
+pub trait Repository {
+    /// 已保存的最新新闻 id，没有新闻时为空
+    fn latest_news_id(&self) -> impl Future<Output = anyhow::Result<Option<u32>>> + Send;
+
+    /// 保存新闻，id 已存在时忽略，返回新增的条数
+    fn save_news(&self, news: &NewsPO) -> impl Future<Output = anyhow::Result<u64>> + Send;
+
+    /// 保存价格，同一时间已有价格时忽略
+    fn save_prices(
+        &self,
+        market: Market,
+        prices: &[(i64, f32)],
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+
+    /// 最近 `limit` 个价格，按时间从早到晚排列
+    fn latest_prices(
+        &self,
+        market: Market,
+        limit: u32,
+    ) -> impl Future<Output = anyhow::Result<Vec<(i64, f32)>>> + Send;
+}
+
+/// 保存价格的行情表
+#[derive(Debug, Clone, Copy)]
+pub enum Market {
+    Gold,
+    Stock,
+}
+
+impl Market {
+    fn table(&self) -> &'static str {
+        match self {
+            Market::Gold => "gold_info",
+            Market::Stock => "stock_info",
+        }
+    }
+}
+
+/// 按 `DATABASE_URL` 的协议选择的数据库，`mysql://` 为 MySQL，`sqlite:` 为单文件的 SQLite
+#[derive(Debug, Clone)]
+pub enum Database {
+    MySql(MySqlPool),
+    Sqlite(SqlitePool),
+}
+
+/// 在两种连接池上执行同一段代码，`$pool` 依次绑定为各自的连接池
+macro_rules! on_pool {
+    ($db:expr, $pool:ident => $body:expr) => {
+        match $db {
+            Database::MySql($pool) => $body,
+            Database::Sqlite($pool) => $body,
+        }
+    };
+}
+pub(crate) use on_pool;
+
+impl Database {
+    /// 连接数据库，SQLite 文件不存在时创建，表由 `migrate` 创建
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        if url.starts_with("mysql:") {
+            return Ok(Database::MySql(MySqlPool::connect(url).await?));
+        }
+        if url.starts_with("sqlite:") {
+            let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+            // 内存库每个连接各自独立，只能用一个连接
+            let max_connections = if url.contains(":memory:") { 1 } else { 4 };
+            let pool = SqlitePoolOptions::new()
+                .max_connections(max_connections)
+                .connect_with(options)
+                .await?;
+            return Ok(Database::Sqlite(pool));
+        }
+        anyhow::bail!("unsupported DATABASE_URL, expected mysql:// or sqlite:")
+    }
+
+    pub fn dialect(&self) -> Dialect {
+        match self {
+            Database::MySql(_) => Dialect::MySql,
+            Database::Sqlite(_) => Dialect::Sqlite,
+        }
+    }
+}
+
+/// MySQL 和 SQLite 写法不同的部分，其余 SQL 两者共用
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    MySql,
+    Sqlite,
+}
+
+impl Dialect {
+    /// 主键已存在时忽略的插入
+    pub fn insert_ignore(&self) -> &'static str {
+        match self {
+            Dialect::MySql => "INSERT IGNORE",
+            Dialect::Sqlite => "INSERT OR IGNORE",
+        }
+    }
+
+    /// 唯一键冲突时用新值覆盖 `columns`，接在 `INSERT ... VALUES` 之后
+    pub fn upsert(&self, key: &str, columns: &[&str]) -> String {
+        match self {
+            Dialect::MySql => {
+                let set: Vec<_> = columns
+                    .iter()
+                    .map(|c| format!("{c} = VALUES({c})"))
+                    .collect();
+                format!(" ON DUPLICATE KEY UPDATE {}", set.join(", "))
+            }
+            Dialect::Sqlite => {
+                let set: Vec<_> = columns
+                    .iter()
+                    .map(|c| format!("{c} = excluded.{c}"))
+                    .collect();
+                format!(" ON CONFLICT ({key}) DO UPDATE SET {}", set.join(", "))
+            }
+        }
+    }
+
+    /// 锁定读到的行直到事务结束，SQLite 同一时间只有一个写事务，不需要行锁
+    pub fn for_update(&self) -> &'static str {
+        match self {
+            Dialect::MySql => " FOR UPDATE",
+            Dialect::Sqlite => "",
+        }
+    }
+
+    /// 整数除法，结果向零取整
+    pub fn int_div(&self) -> &'static str {
+        match self {
+            Dialect::MySql => "DIV",
+            Dialect::Sqlite => "/",
+        }
+    }
+
+    /// 接在 `LIKE ?` 之后，以 `\` 为转义符，MySQL 默认即是，SQLite 需要指定
+    pub fn like_escape(&self) -> &'static str {
+        match self {
+            Dialect::MySql => "",
+            Dialect::Sqlite => " ESCAPE '\\'",
+        }
+    }
+
+    fn save_news_sql(&self) -> String {
+        format!(
+            "{} INTO news (id, content, timestamp, target) VALUES (?, ?, ?, ?)",
+            self.insert_ignore()
+        )
+    }
+
+    /// 一次插入 `rows` 个价格
+    fn save_prices_sql(&self, market: Market, rows: usize) -> String {
+        format!(
+            "{} INTO {} (timestamp, price) VALUES {}",
+            self.insert_ignore(),
+            market.table(),
+            vec!["(?, ?)"; rows].join(", ")
+        )
+    }
+}
+
+const LATEST_NEWS_ID_SQL: &str = "SELECT MAX(id) FROM news";
+
+fn latest_prices_sql(market: Market) -> String {
+    format!(
+        "SELECT * FROM (SELECT timestamp, price FROM {} ORDER BY timestamp DESC LIMIT ?) t
+        ORDER BY timestamp",
+        market.table()
+    )
+}
+
+impl Repository for Database {
+    async fn latest_news_id(&self) -> anyhow::Result<Option<u32>> {
+        let id =
+            on_pool!(self, pool => sqlx::query_scalar(LATEST_NEWS_ID_SQL).fetch_one(pool).await?);
+        Ok(id)
+    }
+
+    async fn save_news(&self, news: &NewsPO) -> anyhow::Result<u64> {
+        let sql = self.dialect().save_news_sql();
+        let result = on_pool!(self, pool => sqlx::query(&sql)
+            .bind(news.id)
+            .bind(&news.content)
+            .bind(news.timestamp)
+            .bind(&news.target)
+            .execute(pool)
+            .await?
+            .rows_affected());
+        Ok(result)
+    }
+
+    async fn save_prices(&self, market: Market, prices: &[(i64, f32)]) -> anyhow::Result<()> {
+        if prices.is_empty() {
+            return Ok(());
+        }
+        let sql = self.dialect().save_prices_sql(market, prices.len());
+        on_pool!(self, pool => {
+            let mut query = sqlx::query(&sql);
+            for (timestamp, price) in prices {
+                query = query.bind(timestamp).bind(price);
+            }
+            query.execute(pool).await?;
+        });
+        Ok(())
+    }
+
+    async fn latest_prices(&self, market: Market, limit: u32) -> anyhow::Result<Vec<(i64, f32)>> {
+        let sql = latest_prices_sql(market);
+        let rows = on_pool!(self, pool => sqlx::query_as(&sql).bind(limit).fetch_all(pool).await?);
+        Ok(rows)
+    }
+}
+
+/// 新插入行的自增 id，MySQL 与 SQLite 的查询结果各有各的写法
+pub trait InsertId {
+    fn insert_id(&self) -> u64;
+}
+
+impl InsertId for MySqlQueryResult {
+    fn insert_id(&self) -> u64 {
+        self.last_insert_id()
+    }
+}
+
+impl InsertId for SqliteQueryResult {
+    fn insert_id(&self) -> u64 {
+        self.last_insert_rowid() as u64
+    }
+}
+
+/// 金额，MySQL 中为 DECIMAL
+///
+/// SQLite 没有定点数，按 REAL 保存，读出时四舍五入到 8 位小数（汇率的精度），消除浮点求和的误差
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Money(pub Decimal);
+
+/// 可以为空的金额列
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NullableMoney(pub Option<Decimal>);
+
+impl From<Money> for Decimal {
+    fn from(money: Money) -> Self {
+        money.0
+    }
+}
+
+impl From<NullableMoney> for Option<Decimal> {
+    fn from(money: NullableMoney) -> Self {
+        money.0
+    }
+}
+
+impl Type<MySql> for Money {
+    fn type_info() -> MySqlTypeInfo {
+        <Decimal as Type<MySql>>::type_info()
+    }
+
+    fn compatible(ty: &MySqlTypeInfo) -> bool {
+        <Decimal as Type<MySql>>::compatible(ty)
+    }
+}
+
+impl Encode<'_, MySql> for Money {
+    fn encode_by_ref(&self, buf: &mut Vec<u8>) -> Result<IsNull, BoxDynError> {
+        <Decimal as Encode<MySql>>::encode_by_ref(&self.0, buf)
+    }
+}
+
+impl Decode<'_, MySql> for Money {
+    fn decode(value: MySqlValueRef<'_>) -> Result<Self, BoxDynError> {
+        Ok(Money(<Decimal as Decode<MySql>>::decode(value)?))
+    }
+}
+
+impl Type<Sqlite> for Money {
+    fn type_info() -> SqliteTypeInfo {
+        <f64 as Type<Sqlite>>::type_info()
+    }
+
+    // 列的亲和性会把整数值的金额存为 INTEGER，求和的结果也可能是 INTEGER
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        matches!(ty.name(), "REAL" | "INTEGER" | "NUMERIC" | "TEXT")
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for Money {
+    fn encode_by_ref(
+        &self,
+        args: &mut Vec<SqliteArgumentValue<'q>>,
+    ) -> Result<IsNull, BoxDynError> {
+        let Some(value) = self.0.to_f64() else {
+            return Err(format!("{} can not be stored as REAL", self.0).into());
+        };
+        <f64 as Encode<Sqlite>>::encode(value, args)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for Money {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let decimal = match value.type_info().name() {
+            "INTEGER" => Decimal::from(<i64 as Decode<Sqlite>>::decode(value)?),
+            // 按最短的十进制表示转换，0.1 读出为 0.1 而不是 0.1000000000000000055
+            "REAL" => <f64 as Decode<Sqlite>>::decode(value)?
+                .to_string()
+                .parse::<Decimal>()?
+                .round_dp(8),
+            _ => <&str as Decode<Sqlite>>::decode(value)?.parse()?,
+        };
+        Ok(Money(decimal))
+    }
+}
+
+impl<DB: sqlx::Database> Type<DB> for NullableMoney
+where
+    Money: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <Money as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <Money as Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'r, DB: sqlx::Database> Decode<'r, DB> for NullableMoney
+where
+    Money: Decode<'r, DB>,
+{
+    fn decode(value: <DB as sqlx::Database>::ValueRef<'r>) -> Result<Self, BoxDynError> {
+        let money = <Option<Money> as Decode<DB>>::decode(value)?;
+        Ok(NullableMoney(money.map(Decimal::from)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    // 连接池的后台任务属于创建它的运行时，每个测试在自己的运行时里连接
+    async fn sqlite() -> Database {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        crate::migrate::run(&db).await.unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn test_latest_prices_in_time_order() {
+        let db = sqlite().await;
+        let prices = [(3, 3.0), (1, 1.0), (2, 2.0)];
+        db.save_prices(Market::Gold, &prices).await.unwrap();
+        // 同一时间的价格忽略
+        db.save_prices(Market::Gold, &[(3, 30.0)]).await.unwrap();
+        let latest = db.latest_prices(Market::Gold, 2).await.unwrap();
+        assert_eq!(latest, vec![(2, 2.0), (3, 3.0)]);
+        assert!(db.latest_prices(Market::Stock, 2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_save_news_once() {
+        let db = sqlite().await;
+        assert_eq!(db.latest_news_id().await.unwrap(), None);
+        let news = NewsPO {
+            id: 3812706,
+            content: "快讯".to_string(),
+            timestamp: 1704040200000,
+            target: "https://xueqiu.com/".to_string(),
+        };
+        assert_eq!(db.save_news(&news).await.unwrap(), 1);
+        assert_eq!(db.save_news(&news).await.unwrap(), 0);
+        assert_eq!(db.latest_news_id().await.unwrap(), Some(3812706));
+    }
+
+    #[test]
+    fn test_dialect_sql() {
+        assert_eq!(
+            Dialect::MySql.save_prices_sql(Market::Gold, 2),
+            "INSERT IGNORE INTO gold_info (timestamp, price) VALUES (?, ?), (?, ?)"
+        );
+        assert!(Dialect::Sqlite
+            .save_news_sql()
+            .starts_with("INSERT OR IGNORE INTO news "));
+        assert_eq!(
+            Dialect::MySql.upsert("currency, timestamp", &["rate"]),
+            " ON DUPLICATE KEY UPDATE rate = VALUES(rate)"
+        );
+        assert_eq!(
+            Dialect::Sqlite.upsert("currency, timestamp", &["rate"]),
+            " ON CONFLICT (currency, timestamp) DO UPDATE SET rate = excluded.rate"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_money_on_sqlite() {
+        let Database::Sqlite(pool) = sqlite().await else {
+            unreachable!()
+        };
+        let sql = "SELECT ? + ?, ?, ?, ?";
+        let (sum, whole, none, text): (Money, Money, NullableMoney, Money) = sqlx::query_as(sql)
+            .bind(Money(dec!(0.1)))
+            .bind(Money(dec!(0.2)))
+            .bind(Money(dec!(12)))
+            .bind(None::<Money>)
+            .bind("3.14")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(sum, Money(dec!(0.3)));
+        assert_eq!(whole, Money(dec!(12)));
+        assert_eq!(none, NullableMoney(None));
+        assert_eq!(text, Money(dec!(3.14)));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_url() {
+        assert!(Database::connect("postgres://localhost/x").await.is_err());
+    }
+}
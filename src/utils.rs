@@ -1,16 +1,28 @@
+use std::time::Duration;
+
 use charts_rs::{svg_to_png, BarChart, Box, SeriesCategory, THEME_ANT};
 use chrono::{Local, TimeZone};
 use chrono_tz::Tz;
 
 pub async fn send_message(content: &str) -> anyhow::Result<()> {
-    let url = format!(
-        "http://106.15.62.248:9901/tXfsoXKwoUD2U9gSoRrJbY/{}",
-        content
-    );
-    reqwest::get(url).await?;
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(message_url(content)?)
+        .send()
+        .await?;
     Ok(())
 }
 
+/// 推送地址，内容编码为一段路径，其中的 `/`、`?`、`#` 不会改变地址
+fn message_url(content: &str) -> anyhow::Result<reqwest::Url> {
+    let mut url = reqwest::Url::parse("http://106.15.62.248:9901/tXfsoXKwoUD2U9gSoRrJbY")?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow::anyhow!("invalid message url"))?
+        .push(content);
+    Ok(url)
+}
+
 /// 获取当前时间
 pub fn currenttime() -> String {
     let timestamp = Local::now().timestamp();
@@ -34,9 +46,24 @@ pub fn create_line_img(
     keys: Vec<String>,
     values: Vec<f32>,
 ) -> anyhow::Result<()> {
-    let axis_min = values.iter().copied().reduce(f32::min).unwrap_or(0.0);
-    let mut bar_chart =
-        BarChart::new_with_theme(vec![(series_name, values).into()], keys, THEME_ANT);
+    let png = create_lines_png(title, keys, vec![(series_name, values)])?;
+    std::fs::write(file_name, png).unwrap();
+    Ok(())
+}
+
+/// 多条折线的 PNG 图片
+pub fn create_lines_png(
+    title: &str,
+    keys: Vec<String>,
+    series: Vec<(&str, Vec<f32>)>,
+) -> anyhow::Result<Vec<u8>> {
+    let axis_min = series
+        .iter()
+        .flat_map(|(_, values)| values.iter().copied())
+        .reduce(f32::min)
+        .unwrap_or(0.0);
+    let series_list = series.into_iter().map(|series| series.into()).collect();
+    let mut bar_chart = BarChart::new_with_theme(series_list, keys, THEME_ANT);
     bar_chart.y_axis_configs[0].axis_min = Some((axis_min / 100f32).floor() * 100f32);
 
     bar_chart.width = 1000.0;
@@ -46,12 +73,12 @@ pub fn create_line_img(
         bottom: 5.0,
         ..Default::default()
     });
-    bar_chart.series_list[0].category = Some(SeriesCategory::Line);
-    bar_chart.series_list[0].y_axis_index = 1;
-    bar_chart.series_list[0].label_show = false;
-    let png = svg_to_png(&bar_chart.svg()?)?;
-    std::fs::write(file_name, png).unwrap();
-    Ok(())
+    for series in bar_chart.series_list.iter_mut() {
+        series.category = Some(SeriesCategory::Line);
+        series.y_axis_index = 1;
+        series.label_show = false;
+    }
+    Ok(svg_to_png(&bar_chart.svg()?)?)
 }
 
 #[cfg(test)]
@@ -63,4 +90,11 @@ mod tests {
         let time = currenttime();
         println!("now: {time}");
     }
+
+    #[test]
+    fn test_message_url_encodes_content() {
+        let url = message_url("预算提醒 餐饮/外卖 #1?").unwrap();
+        assert_eq!(url.path_segments().unwrap().count(), 2);
+        assert!(url.query().is_none() && url.fragment().is_none());
+    }
 }
@@ -12,7 +12,14 @@ pub async fn get() -> anyhow::Result<Vec<String>> {
     let document = scraper::Html::parse_document(&html);
     let selector = scraper::Selector::parse(r#"ul[class="weaul"] > li"#).unwrap();
     for element in document.select(&selector) {
-        let weather = element.text().collect::<String>().trim().to_string().trim_end_matches("\n查看天气详情").to_string().replace("\n", " ");
+        let weather = element
+            .text()
+            .collect::<String>()
+            .trim()
+            .to_string()
+            .trim_end_matches("\n查看天气详情")
+            .to_string()
+            .replace("\n", " ");
         weathers.push(weather);
     }
     Ok(weathers)
@@ -27,4 +34,4 @@ mod tests {
         let weathers = tokio_test::block_on(get()).unwrap();
         println!("{:?}", weathers);
     }
-}
\ No newline at end of file
+}